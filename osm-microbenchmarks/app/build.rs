@@ -27,9 +27,26 @@
 // OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use std::env;
+use std::process::Command;
 
 fn main () {
 
+    // Recorded in the metadata of every result.
+    let commit = Command::new("git")
+        .args(&["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=BUILD_GIT_COMMIT={}", if commit.is_empty() { "unknown" } else { &commit });
+    println!("cargo:rustc-env=BUILD_PROFILE={}", env::var("PROFILE").unwrap_or_default());
+
+    // The native backend links the trusted code directly.
+    if env::var("CARGO_FEATURE_NATIVE").is_ok() {
+        return;
+    }
+
     let sdk_dir = env::var("SGX_SDK")
                     .unwrap_or_else(|_| "/opt/intel/sgxsdk".to_string());
     
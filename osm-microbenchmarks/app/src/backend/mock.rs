@@ -0,0 +1,354 @@
+//! A backend that records the ECALLs it receives instead of executing them.
+//!
+//! Nothing is stored: every call succeeds (or fails with the status set by
+//! `fail_with`) and returns an empty answer. Searches find no values, and
+//! every operation in a batch succeeds with no values, in 0 cycles (or
+//! `UNTIMED`, after `set_untimed`). This is enough to check which ECALLs a
+//! benchmark makes, and in what order, without an enclave or the trusted
+//! code.
+
+use std::sync::{Arc, Mutex};
+
+use backend::EnclaveBackend;
+use batch::OP_WORDS;
+use errors::{BenchError, OsmStatus};
+use microbenchmarks::{Key, Value};
+use timing::UNTIMED;
+
+/// One recorded ECALL. Buffers are recorded by length only, except for
+/// batches, whose encoded operations are kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Call {
+    OsmOpen { client_len: usize, server_ref: usize },
+    OsmClose { session_id: u64 },
+    OsmSeal { session_id: u64 },
+    OsmUnseal { sealed_len: usize, server_ref: usize },
+    OsmSearch { session_id: u64, key: Key, range: usize },
+    OsmInsertMany { session_id: u64, n_keys: usize },
+    OsmInsertOne { session_id: u64, key: Key, value: Value },
+    OsmDeleteOne { session_id: u64, key: Key, value: Value },
+    OsmExecuteBatch { session_id: u64, ops: Vec<u64> },
+    OsmMultiSearch { session_id: u64, keys: Vec<Key>, conjunctive: bool, max_postings: usize },
+    OramZeroTrace { n_reads: usize },
+    OramAccess { block_size: usize, n_reads: usize },
+}
+
+#[derive(Default)]
+struct State {
+    calls: Vec<Call>,
+    next_session: u64,
+    failure: Option<OsmStatus>,
+    untimed: bool,
+}
+
+/// Clones share the same log.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<State>>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        MockBackend::default()
+    }
+
+    /// Makes every subsequent call fail with `status`, or succeed again
+    /// with `None`. Calls are recorded either way.
+    pub fn fail_with(&self, status: Option<OsmStatus>) {
+        self.state.lock().unwrap().failure = status;
+    }
+
+    /// Makes batches return `UNTIMED` cycle counts, as an enclave built
+    /// without `enclave_timing` does, or 0 cycles per operation again.
+    pub fn set_untimed(&self, untimed: bool) {
+        self.state.lock().unwrap().untimed = untimed;
+    }
+
+    /// The calls received so far, oldest first.
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    fn record(&self, call: Call) -> Result<(), BenchError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(call);
+        match state.failure {
+            Some(status) => Err(BenchError::Osm(status)),
+            None => Ok(()),
+        }
+    }
+
+    fn new_session(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_session += 1;
+        state.next_session
+    }
+}
+
+impl EnclaveBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn osm_open(&self, client: &[u8], server_ref: usize) -> Result<u64, BenchError> {
+        self.record(Call::OsmOpen { client_len: client.len(), server_ref })?;
+        Ok(self.new_session())
+    }
+
+    fn osm_close(&self, session_id: u64) -> Result<(), BenchError> {
+        self.record(Call::OsmClose { session_id })
+    }
+
+    fn osm_seal(&self, session_id: u64, _sealed: &mut [u8], sealed_len: &mut usize) -> Result<(), BenchError> {
+        self.record(Call::OsmSeal { session_id })?;
+        *sealed_len = 0;
+        Ok(())
+    }
+
+    fn osm_unseal(&self, sealed: &[u8], server_ref: usize) -> Result<u64, BenchError> {
+        self.record(Call::OsmUnseal { sealed_len: sealed.len(), server_ref })?;
+        Ok(self.new_session())
+    }
+
+    fn osm_search(&self, session_id: u64, key: Key, range: usize, _values: &mut [Value]) -> Result<usize, BenchError> {
+        self.record(Call::OsmSearch { session_id, key, range })?;
+        Ok(0)
+    }
+
+    fn osm_insert_many(&self, session_id: u64, keys: &[Key], values: &[Value]) -> Result<(), BenchError> {
+        self.record(Call::OsmInsertMany { session_id, n_keys: keys.len() })?;
+        if keys.len() != values.len() {
+            return Err(BenchError::Osm(OsmStatus::InvalidInput));
+        }
+        Ok(())
+    }
+
+    fn osm_insert_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError> {
+        self.record(Call::OsmInsertOne { session_id, key, value })
+    }
+
+    fn osm_delete_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError> {
+        self.record(Call::OsmDeleteOne { session_id, key, value })
+    }
+
+    fn osm_execute_batch(&self, session_id: u64, ops: &[u64], results: &mut [u64], cycles: &mut [u64]) -> Result<usize, BenchError> {
+        self.record(Call::OsmExecuteBatch { session_id, ops: ops.to_vec() })?;
+        let n_ops = ops.len() / OP_WORDS;
+        if ops.len() % OP_WORDS != 0 || results.len() < 2 * n_ops || cycles.len() < n_ops {
+            return Err(BenchError::Osm(OsmStatus::InvalidInput));
+        }
+        // `| Success | 0 |` for every operation.
+        for word in results[..2 * n_ops].iter_mut() {
+            *word = 0;
+        }
+        let cycle_count = if self.state.lock().unwrap().untimed { UNTIMED } else { 0 };
+        for c in cycles[..n_ops].iter_mut() {
+            *c = cycle_count;
+        }
+        Ok(2 * n_ops)
+    }
+
+    fn osm_multi_search(
+        &self,
+        session_id: u64,
+        keys: &[Key],
+        conjunctive: bool,
+        max_postings: usize,
+        _values: &mut [Value],
+        cycles: &mut u64,
+    ) -> Result<usize, BenchError> {
+        self.record(Call::OsmMultiSearch { session_id, keys: keys.to_vec(), conjunctive, max_postings })?;
+        *cycles = 0;
+        Ok(0)
+    }
+
+    fn oram_zerotrace(&self, _client: &[u8], _server_ref: usize, _key_and_pos: &[u8], cycles: &mut [u64]) -> Result<(), BenchError> {
+        self.record(Call::OramZeroTrace { n_reads: cycles.len() })
+    }
+
+    fn oram_access(&self, _client: &[u8], _server_ref: usize, _key_and_pos: &[u8], block_size: usize, cycles: &mut [u64]) -> Result<(), BenchError> {
+        self.record(Call::OramAccess { block_size, n_reads: cycles.len() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use backend::TracingBackend;
+    use batch::{self, Op, OP_WORDS};
+    use errors::{BenchError, OsmStatus};
+    use key_transparency;
+    use microbenchmarks::{self, workload_rng};
+    use replay;
+    use timing::Clock;
+    use trace::{self, TraceOp};
+
+    use super::{Call, MockBackend};
+
+    fn decode_ops(words: &[u64]) -> Vec<Op> {
+        words.chunks(OP_WORDS).map(|op| Op::decode(op).unwrap()).collect()
+    }
+
+    /// The operations of every batch the backend received.
+    fn batches(backend: &MockBackend) -> Vec<Vec<Op>> {
+        backend.calls().into_iter()
+            .filter_map(|call| match call {
+                Call::OsmExecuteBatch { ops, .. } => Some(decode_ops(&ops)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn insert_one_opens_inserts_and_closes() {
+        let backend = MockBackend::new();
+        let latencies = microbenchmarks::insert_one(&backend, &mut workload_rng(1), 16, 3).unwrap();
+        assert_eq!(latencies.clock, Clock::Host);
+        assert_eq!(latencies.secs.len(), 3);
+
+        let calls = backend.calls();
+        assert_eq!(calls.len(), 5);
+        match calls[0] {
+            Call::OsmOpen { client_len, .. } => assert!(client_len > 0),
+            ref call => panic!("expected an open, got {:?}", call),
+        }
+        for call in &calls[1..4] {
+            match *call {
+                Call::OsmInsertOne { session_id, .. } => assert_eq!(session_id, 1),
+                ref call => panic!("expected an insert, got {:?}", call),
+            }
+        }
+        assert_eq!(calls[4], Call::OsmClose { session_id: 1 });
+    }
+
+    #[test]
+    fn same_seed_same_calls() {
+        let run = |seed| {
+            let backend = MockBackend::new();
+            microbenchmarks::insert_one(&backend, &mut workload_rng(seed), 16, 3).unwrap();
+            backend.calls().into_iter()
+                .filter(|call| match *call { Call::OsmInsertOne { .. } => true, _ => false })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(7), run(7));
+        assert!(run(7) != run(8));
+    }
+
+    #[test]
+    fn failures_propagate() {
+        let backend = MockBackend::new();
+        backend.fail_with(Some(OsmStatus::InvalidSession));
+        let result = microbenchmarks::insert_one(&backend, &mut workload_rng(1), 16, 3);
+        assert_eq!(result, Err(BenchError::Osm(OsmStatus::InvalidSession)));
+        // Nothing is attempted once the session could not be opened.
+        assert_eq!(backend.calls().len(), 1);
+
+        backend.fail_with(None);
+        assert!(microbenchmarks::insert_one(&backend, &mut workload_rng(1), 16, 3).is_ok());
+    }
+
+    #[test]
+    fn batches_reach_the_backend_as_encoded() {
+        let backend = MockBackend::new();
+        let ops = vec![
+            Op::Search { key: 1, offset: 2, range: 3 },
+            Op::Insert { key: 4, value: 5 },
+            Op::Delete { key: 6, value: 7 },
+            Op::Update { key: 8, old: 9, new: 10 },
+        ];
+        let output = microbenchmarks::execute_batch(&backend, 1, &ops).unwrap();
+        assert_eq!(output.results, vec![Ok(vec![]); 4]);
+        assert_eq!(output.group_latencies(batch::singles(4)).secs.len(), 4);
+        match backend.calls()[0] {
+            Call::OsmExecuteBatch { session_id, ops: ref words } => {
+                assert_eq!(session_id, 1);
+                assert_eq!(decode_ops(words), ops);
+            },
+            ref call => panic!("expected a batch, got {:?}", call),
+        }
+    }
+
+    #[test]
+    fn lookups_that_do_not_verify_fail() {
+        // The mock finds nothing, so no binding can be checked against the
+        // root.
+        let backend = MockBackend::new();
+        let result = key_transparency::lookup(&backend, &mut workload_rng(1), 8);
+        assert_eq!(result, Err(BenchError::IncorrectResult));
+    }
+
+    #[test]
+    fn recorded_traces_replay_with_their_pairs() {
+        let path = env::temp_dir().join(format!("osm-mock-trace-{}", process::id()));
+        let path = path.to_str().unwrap();
+        {
+            let backend = TracingBackend::recording(MockBackend::new(), path).unwrap();
+            microbenchmarks::insert_one(&backend, &mut workload_rng(1), 16, 3).unwrap();
+        }
+        let entries = trace::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let loads = entries.iter().filter(|e| match e.op { TraceOp::Load { .. } => true, _ => false }).count();
+        let inserts: Vec<Op> = entries.iter()
+            .filter_map(|e| match e.op { TraceOp::Osm(op) => Some(op), _ => None })
+            .collect();
+        assert_eq!(loads, 16);
+        assert_eq!(inserts.len(), 3);
+
+        let backend = MockBackend::new();
+        let latencies = replay::osm(&backend, &mut workload_rng(1), &entries, 0, false).unwrap();
+        assert_eq!(latencies.secs.len(), 3);
+        assert_eq!(batches(&backend).concat(), inserts);
+    }
+
+    #[test]
+    fn timed_groups_share_a_batch_after_the_first() {
+        let backend = MockBackend::new();
+        let ops: Vec<Op> = (0..6).map(|key| Op::Insert { key, value: 0 }).collect();
+        let (results, latencies) = microbenchmarks::execute_groups(&backend, 1, &ops, &[1..3, 3..5]).unwrap();
+        assert_eq!(results.len(), 6);
+        assert_eq!(latencies.clock, Clock::Enclave);
+        assert_eq!(latencies.secs.len(), 2);
+        assert_eq!(batches(&backend), vec![ops[..1].to_vec(), ops[1..3].to_vec(), ops[3..].to_vec()]);
+    }
+
+    #[test]
+    fn untimed_groups_get_batches_of_their_own() {
+        let backend = MockBackend::new();
+        backend.set_untimed(true);
+        let ops: Vec<Op> = (0..6).map(|key| Op::Insert { key, value: 0 }).collect();
+        let (results, latencies) = microbenchmarks::execute_groups(&backend, 1, &ops, &[1..3, 3..5]).unwrap();
+        assert_eq!(results.len(), 6);
+        assert_eq!(latencies.clock, Clock::Host);
+        assert_eq!(latencies.secs.len(), 2);
+        assert_eq!(
+            batches(&backend),
+            vec![ops[..1].to_vec(), ops[1..3].to_vec(), ops[3..5].to_vec(), ops[5..].to_vec()]
+        );
+    }
+
+    #[test]
+    fn groups_must_be_ordered_ranges_of_the_batch() {
+        let backend = MockBackend::new();
+        let ops: Vec<Op> = (0..6).map(|key| Op::Insert { key, value: 0 }).collect();
+        for groups in [vec![2..4, 1..2], vec![0..7]].iter() {
+            let result = microbenchmarks::execute_groups(&backend, 1, &ops, groups);
+            assert_eq!(result.err(), Some(BenchError::Osm(OsmStatus::InvalidInput)));
+        }
+        assert!(backend.calls().is_empty());
+    }
+
+    #[test]
+    fn throughput_closes_every_session() {
+        let backend = MockBackend::new();
+        let latencies = microbenchmarks::throughput(&backend, &mut workload_rng(1), 3, 16, 2).unwrap();
+        assert_eq!(latencies.secs.len(), 6);
+        let calls = backend.calls();
+        let closed: Vec<u64> = calls.iter()
+            .filter_map(|call| match *call { Call::OsmClose { session_id } => Some(session_id), _ => None })
+            .collect();
+        assert_eq!(closed, vec![1, 2, 3]);
+    }
+}
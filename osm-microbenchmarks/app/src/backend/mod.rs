@@ -0,0 +1,87 @@
+//! What the benchmarks run against.
+//!
+//! Every benchmark is written against `EnclaveBackend`, which has one method
+//! per ECALL in `Enclave.edl` with slices in place of pointer/length pairs.
+//! The backend compiled into the binary is chosen by cargo feature: the
+//! real enclave by default, or the trusted code linked in-process with
+//! `native`. `MockBackend` needs neither and is only built for the tests of
+//! the harness itself, and `TracingBackend` wraps any backend to record the
+//! operations it executes.
+
+use errors::BenchError;
+use microbenchmarks::{Key, Value};
+
+#[cfg(test)]
+pub mod mock;
+#[cfg(feature = "native")]
+pub mod native;
+#[cfg(not(feature = "native"))]
+pub mod sgx;
+pub mod tracing;
+
+#[cfg(test)]
+pub use self::mock::MockBackend;
+#[cfg(feature = "native")]
+pub use self::native::NativeBackend;
+#[cfg(not(feature = "native"))]
+pub use self::sgx::SgxBackend;
+pub use self::tracing::TracingBackend;
+
+/// A way of executing the trusted entry points.
+///
+/// Backends are cheap handles: benchmarks clone them into worker threads
+/// and may issue ECALLs from several threads at once.
+pub trait EnclaveBackend: Clone + Send + Sync + 'static {
+    /// Label attached to every reported result.
+    fn name(&self) -> &'static str;
+
+    /// Copies an encoded client into the enclave and returns the id of the
+    /// session that now owns it. The server at `server_ref` must outlive
+    /// the session.
+    fn osm_open(&self, client: &[u8], server_ref: usize) -> Result<u64, BenchError>;
+
+    fn osm_close(&self, session_id: u64) -> Result<(), BenchError>;
+
+    /// Not an ECALL: called with the pairs a store is set up with, before
+    /// the session over it is opened. Only `TracingBackend` needs them, to
+    /// record them.
+    fn osm_preload(&self, _pairs: &[(Key, Value)]) {}
+
+    /// Seals the session's client into `sealed`. On success, and on
+    /// `BufferTooSmall`, `sealed_len` is set to the size of the sealed
+    /// state.
+    fn osm_seal(&self, session_id: u64, sealed: &mut [u8], sealed_len: &mut usize) -> Result<(), BenchError>;
+
+    fn osm_unseal(&self, sealed: &[u8], server_ref: usize) -> Result<u64, BenchError>;
+
+    /// Returns the number of values written to `values`.
+    fn osm_search(&self, session_id: u64, key: Key, range: usize, values: &mut [Value]) -> Result<usize, BenchError>;
+
+    fn osm_insert_many(&self, session_id: u64, keys: &[Key], values: &[Value]) -> Result<(), BenchError>;
+
+    fn osm_insert_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError>;
+
+    fn osm_delete_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError>;
+
+    /// Executes an encoded batch (see `batch.rs`) and returns the number of
+    /// result words written.
+    fn osm_execute_batch(&self, session_id: u64, ops: &[u64], results: &mut [u64], cycles: &mut [u64]) -> Result<usize, BenchError>;
+
+    /// Writes the intersection (`conjunctive`) or union of the values of
+    /// `keys`, each searched with range `max_postings`, to `values` and
+    /// returns its size. `cycles` is set to the in-enclave time of the
+    /// query.
+    fn osm_multi_search(
+        &self,
+        session_id: u64,
+        keys: &[Key],
+        conjunctive: bool,
+        max_postings: usize,
+        values: &mut [Value],
+        cycles: &mut u64,
+    ) -> Result<usize, BenchError>;
+
+    fn oram_zerotrace(&self, client: &[u8], server_ref: usize, key_and_pos: &[u8], cycles: &mut [u64]) -> Result<(), BenchError>;
+
+    fn oram_access(&self, client: &[u8], server_ref: usize, key_and_pos: &[u8], block_size: usize, cycles: &mut [u64]) -> Result<(), BenchError>;
+}
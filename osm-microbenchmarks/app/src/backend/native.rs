@@ -0,0 +1,124 @@
+//! In-process execution of the trusted code, for hosts without SGX.
+//!
+//! With the `native` feature the app links the enclave crate as an ordinary
+//! library instead of loading `enclave.signed.so`, and this backend calls
+//! the trusted entry points directly. Nothing is copied across a boundary
+//! and no enclave transition is paid, so timings from this backend are not
+//! comparable with SGX timings.
+
+use osm_microbenchmarks as trusted;
+
+use backend::EnclaveBackend;
+use errors::BenchError;
+use microbenchmarks::{Key, Value};
+use sgx_types::sgx_status_t;
+
+fn check(retval: u32) -> Result<(), BenchError> {
+    ::errors::check(sgx_status_t::SGX_SUCCESS, retval)
+}
+
+#[derive(Clone, Copy)]
+pub struct NativeBackend;
+
+impl EnclaveBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn osm_open(&self, client: &[u8], server_ref: usize) -> Result<u64, BenchError> {
+        let mut session_id = 0;
+        check(trusted::osm_open(client.as_ptr(), client.len(), server_ref, &mut session_id)).map(|_| session_id)
+    }
+
+    fn osm_close(&self, session_id: u64) -> Result<(), BenchError> {
+        check(trusted::osm_close(session_id))
+    }
+
+    fn osm_seal(&self, session_id: u64, sealed: &mut [u8], sealed_len: &mut usize) -> Result<(), BenchError> {
+        check(trusted::osm_seal(session_id, sealed.as_mut_ptr(), sealed.len(), sealed_len))
+    }
+
+    fn osm_unseal(&self, sealed: &[u8], server_ref: usize) -> Result<u64, BenchError> {
+        let mut session_id = 0;
+        check(trusted::osm_unseal(sealed.as_ptr(), sealed.len(), server_ref, &mut session_id)).map(|_| session_id)
+    }
+
+    fn osm_search(&self, session_id: u64, key: Key, range: usize, values: &mut [Value]) -> Result<usize, BenchError> {
+        let mut count = 0;
+        check(trusted::osm_search(session_id, key, range, values.as_mut_ptr(), values.len(), &mut count)).map(|_| count)
+    }
+
+    fn osm_insert_many(&self, session_id: u64, keys: &[Key], values: &[Value]) -> Result<(), BenchError> {
+        check(trusted::osm_insert_many(session_id, keys.as_ptr(), keys.len(), values.as_ptr(), values.len()))
+    }
+
+    fn osm_insert_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError> {
+        check(trusted::osm_insert_one(session_id, key, value))
+    }
+
+    fn osm_delete_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError> {
+        check(trusted::osm_delete_one(session_id, key, value))
+    }
+
+    fn osm_execute_batch(&self, session_id: u64, ops: &[u64], results: &mut [u64], cycles: &mut [u64]) -> Result<usize, BenchError> {
+        let mut results_len = 0;
+        check(trusted::osm_execute_batch(
+            session_id,
+            ops.as_ptr(),
+            ops.len(),
+            results.as_mut_ptr(),
+            results.len(),
+            &mut results_len,
+            cycles.as_mut_ptr(),
+            cycles.len(),
+        )).map(|_| results_len)
+    }
+
+    fn osm_multi_search(
+        &self,
+        session_id: u64,
+        keys: &[Key],
+        conjunctive: bool,
+        max_postings: usize,
+        values: &mut [Value],
+        cycles: &mut u64,
+    ) -> Result<usize, BenchError> {
+        let mut count = 0;
+        check(trusted::osm_multi_search(
+            session_id,
+            keys.as_ptr(),
+            keys.len(),
+            conjunctive as u32,
+            max_postings,
+            values.as_mut_ptr(),
+            values.len(),
+            &mut count,
+            cycles,
+        )).map(|_| count)
+    }
+
+    fn oram_zerotrace(&self, client: &[u8], server_ref: usize, key_and_pos: &[u8], cycles: &mut [u64]) -> Result<(), BenchError> {
+        check(trusted::oram_zerotrace(
+            client.as_ptr(),
+            client.len(),
+            server_ref,
+            key_and_pos.as_ptr(),
+            key_and_pos.len(),
+            cycles.as_mut_ptr(),
+            cycles.len(),
+        ))
+    }
+
+    fn oram_access(&self, client: &[u8], server_ref: usize, key_and_pos: &[u8], block_size: usize, cycles: &mut [u64]) -> Result<(), BenchError> {
+        check(trusted::oram_access(
+            client.as_ptr(),
+            client.len(),
+            server_ref,
+            key_and_pos.as_ptr(),
+            key_and_pos.len(),
+            block_size,
+            cycles.as_mut_ptr(),
+            cycles.len(),
+        ))
+    }
+}
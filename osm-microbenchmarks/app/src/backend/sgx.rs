@@ -0,0 +1,299 @@
+//! The real enclave, reached through the untrusted proxies generated by
+//! `sgx_edger8r` from `Enclave.edl`.
+
+use sgx_types::*;
+use sgx_urts::SgxEnclave;
+
+use backend::EnclaveBackend;
+use errors::{self, BenchError};
+use microbenchmarks::{Key, Value};
+
+extern {
+    fn osm_open(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        client: *const u8,
+        client_len: usize,
+        server_ref: usize,
+        session_id: *mut u64
+    ) -> sgx_status_t;
+
+    fn osm_close(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        session_id: u64
+    ) -> sgx_status_t;
+
+    fn osm_seal(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        session_id: u64,
+        sealed: *mut u8,
+        sealed_cap: usize,
+        sealed_len: *mut usize
+    ) -> sgx_status_t;
+
+    fn osm_unseal(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        sealed: *const u8,
+        sealed_len: usize,
+        server_ref: usize,
+        session_id: *mut u64
+    ) -> sgx_status_t;
+
+    fn osm_search(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        session_id: u64,
+        key: Key,
+        range: usize,
+        values: *mut Value,
+        max_values: usize,
+        count: *mut usize
+    ) -> sgx_status_t;
+
+    fn osm_insert_many(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        session_id: u64,
+        keys: *const Key,
+        keys_len: usize,
+        vals: *const Value,
+        vals_len: usize
+    ) -> sgx_status_t;
+
+    fn osm_insert_one(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        session_id: u64,
+        key: Key,
+        value: Value
+    ) -> sgx_status_t;
+
+    fn osm_delete_one(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        session_id: u64,
+        key: Key,
+        value: Value
+    ) -> sgx_status_t;
+
+    fn osm_execute_batch(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        session_id: u64,
+        ops: *const u64,
+        ops_len: usize,
+        results: *mut u64,
+        results_cap: usize,
+        results_len: *mut usize,
+        cycles: *mut u64,
+        cycles_len: usize
+    ) -> sgx_status_t;
+
+    fn osm_multi_search(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        session_id: u64,
+        keys: *const Key,
+        keys_len: usize,
+        conjunctive: u32,
+        max_postings: usize,
+        values: *mut Value,
+        max_values: usize,
+        count: *mut usize,
+        cycles: *mut u64
+    ) -> sgx_status_t;
+
+    fn oram_zerotrace(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        client: *const u8,
+        client_len: usize,
+        server_ref: usize,
+        key_and_pos: *const u8,
+        key_and_pos_len: usize,
+        cycles: *mut u64,
+        cycles_len: usize
+    ) -> sgx_status_t;
+
+    fn oram_access(
+        eid: sgx_enclave_id_t,
+        retval: *mut u32,
+        client: *const u8,
+        client_len: usize,
+        server_ref: usize,
+        key_and_pos: *const u8,
+        key_and_pos_len: usize,
+        block_size: usize,
+        cycles: *mut u64,
+        cycles_len: usize,
+    ) -> sgx_status_t;
+}
+
+/// Issues ECALLs to a loaded enclave. This is only a handle: the enclave
+/// itself is owned (and destroyed) by whoever created it.
+#[derive(Clone, Copy)]
+pub struct SgxBackend {
+    eid: sgx_enclave_id_t,
+}
+
+impl SgxBackend {
+    pub fn new(enclave: &SgxEnclave) -> Self {
+        SgxBackend { eid: enclave.geteid() }
+    }
+}
+
+impl EnclaveBackend for SgxBackend {
+    fn name(&self) -> &'static str {
+        "sgx"
+    }
+
+    fn osm_open(&self, client: &[u8], server_ref: usize) -> Result<u64, BenchError> {
+        let mut session_id = 0;
+        let mut retval = 0;
+        let result = unsafe {
+            osm_open(self.eid, &mut retval, client.as_ptr(), client.len(), server_ref, &mut session_id)
+        };
+        errors::check(result, retval).map(|_| session_id)
+    }
+
+    fn osm_close(&self, session_id: u64) -> Result<(), BenchError> {
+        let mut retval = 0;
+        let result = unsafe { osm_close(self.eid, &mut retval, session_id) };
+        errors::check(result, retval)
+    }
+
+    fn osm_seal(&self, session_id: u64, sealed: &mut [u8], sealed_len: &mut usize) -> Result<(), BenchError> {
+        let mut retval = 0;
+        let result = unsafe {
+            osm_seal(self.eid, &mut retval, session_id, sealed.as_mut_ptr(), sealed.len(), sealed_len)
+        };
+        errors::check(result, retval)
+    }
+
+    fn osm_unseal(&self, sealed: &[u8], server_ref: usize) -> Result<u64, BenchError> {
+        let mut session_id = 0;
+        let mut retval = 0;
+        let result = unsafe {
+            osm_unseal(self.eid, &mut retval, sealed.as_ptr(), sealed.len(), server_ref, &mut session_id)
+        };
+        errors::check(result, retval).map(|_| session_id)
+    }
+
+    fn osm_search(&self, session_id: u64, key: Key, range: usize, values: &mut [Value]) -> Result<usize, BenchError> {
+        let mut count = 0;
+        let mut retval = 0;
+        let result = unsafe {
+            osm_search(self.eid, &mut retval, session_id, key, range, values.as_mut_ptr(), values.len(), &mut count)
+        };
+        errors::check(result, retval).map(|_| count)
+    }
+
+    fn osm_insert_many(&self, session_id: u64, keys: &[Key], values: &[Value]) -> Result<(), BenchError> {
+        let mut retval = 0;
+        let result = unsafe {
+            osm_insert_many(self.eid, &mut retval, session_id, keys.as_ptr(), keys.len(), values.as_ptr(), values.len())
+        };
+        errors::check(result, retval)
+    }
+
+    fn osm_insert_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError> {
+        let mut retval = 0;
+        let result = unsafe { osm_insert_one(self.eid, &mut retval, session_id, key, value) };
+        errors::check(result, retval)
+    }
+
+    fn osm_delete_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError> {
+        let mut retval = 0;
+        let result = unsafe { osm_delete_one(self.eid, &mut retval, session_id, key, value) };
+        errors::check(result, retval)
+    }
+
+    fn osm_execute_batch(&self, session_id: u64, ops: &[u64], results: &mut [u64], cycles: &mut [u64]) -> Result<usize, BenchError> {
+        let mut results_len = 0;
+        let mut retval = 0;
+        let result = unsafe {
+            osm_execute_batch(
+                self.eid,
+                &mut retval,
+                session_id,
+                ops.as_ptr(),
+                ops.len(),
+                results.as_mut_ptr(),
+                results.len(),
+                &mut results_len,
+                cycles.as_mut_ptr(),
+                cycles.len(),
+            )
+        };
+        errors::check(result, retval).map(|_| results_len)
+    }
+
+    fn osm_multi_search(
+        &self,
+        session_id: u64,
+        keys: &[Key],
+        conjunctive: bool,
+        max_postings: usize,
+        values: &mut [Value],
+        cycles: &mut u64,
+    ) -> Result<usize, BenchError> {
+        let mut count = 0;
+        let mut retval = 0;
+        let result = unsafe {
+            osm_multi_search(
+                self.eid,
+                &mut retval,
+                session_id,
+                keys.as_ptr(),
+                keys.len(),
+                conjunctive as u32,
+                max_postings,
+                values.as_mut_ptr(),
+                values.len(),
+                &mut count,
+                cycles,
+            )
+        };
+        errors::check(result, retval).map(|_| count)
+    }
+
+    fn oram_zerotrace(&self, client: &[u8], server_ref: usize, key_and_pos: &[u8], cycles: &mut [u64]) -> Result<(), BenchError> {
+        let mut retval = 0;
+        let result = unsafe {
+            oram_zerotrace(
+                self.eid,
+                &mut retval,
+                client.as_ptr(),
+                client.len(),
+                server_ref,
+                key_and_pos.as_ptr(),
+                key_and_pos.len(),
+                cycles.as_mut_ptr(),
+                cycles.len(),
+            )
+        };
+        errors::check(result, retval)
+    }
+
+    fn oram_access(&self, client: &[u8], server_ref: usize, key_and_pos: &[u8], block_size: usize, cycles: &mut [u64]) -> Result<(), BenchError> {
+        let mut retval = 0;
+        let result = unsafe {
+            oram_access(
+                self.eid,
+                &mut retval,
+                client.as_ptr(),
+                client.len(),
+                server_ref,
+                key_and_pos.as_ptr(),
+                key_and_pos.len(),
+                block_size,
+                cycles.as_mut_ptr(),
+                cycles.len(),
+            )
+        };
+        errors::check(result, retval)
+    }
+}
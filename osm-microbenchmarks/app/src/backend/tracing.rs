@@ -0,0 +1,137 @@
+//! A backend that forwards every ECALL to another backend and, when asked
+//! to, appends the OSM and ORAM operations it carries to a trace file (see
+//! `trace.rs`), along with the pairs every store starts out with. Wrapping
+//! the backend is all it takes to record the trace of any benchmark.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use bincode;
+use path_oram::{OramKey, OramPos};
+
+use backend::EnclaveBackend;
+use batch::{Op, OP_WORDS};
+use errors::BenchError;
+use microbenchmarks::{Key, Value};
+use trace::{self, TraceOp, TraceWriter};
+
+#[derive(Clone)]
+pub struct TracingBackend<B> {
+    inner: B,
+    trace: Option<Arc<Mutex<TraceWriter>>>,
+}
+
+impl<B: EnclaveBackend> TracingBackend<B> {
+    /// Forwards to `inner` without recording anything.
+    pub fn new(inner: B) -> Self {
+        TracingBackend { inner, trace: None }
+    }
+
+    /// Forwards to `inner` and records the operations to `path`.
+    pub fn recording(inner: B, path: &str) -> io::Result<Self> {
+        let writer = TraceWriter::create(path)?;
+        Ok(TracingBackend { inner, trace: Some(Arc::new(Mutex::new(writer))) })
+    }
+
+    fn record<I: IntoIterator<Item = TraceOp>>(&self, ops: I) {
+        if let Some(ref trace) = self.trace {
+            let mut trace = trace.lock().unwrap();
+            for op in ops {
+                if let Err(e) = trace.write(op) {
+                    progress!("[-] Could not write to the trace: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn record_oram(&self, key_and_pos: &[u8]) {
+        if self.trace.is_none() {
+            return;
+        }
+        let keys_and_positions: Vec<(OramKey, OramPos)> = match bincode::deserialize(key_and_pos) {
+            Ok(keys_and_positions) => keys_and_positions,
+            Err(_) => return,
+        };
+        self.record(keys_and_positions.iter()
+            .filter_map(|&(ref key, _)| trace::oram_key_index(key))
+            .map(TraceOp::Access));
+    }
+}
+
+impl<B: EnclaveBackend> EnclaveBackend for TracingBackend<B> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn osm_open(&self, client: &[u8], server_ref: usize) -> Result<u64, BenchError> {
+        self.inner.osm_open(client, server_ref)
+    }
+
+    fn osm_close(&self, session_id: u64) -> Result<(), BenchError> {
+        self.inner.osm_close(session_id)
+    }
+
+    fn osm_preload(&self, pairs: &[(Key, Value)]) {
+        self.record(pairs.iter().map(|&(key, value)| TraceOp::Load { key, value }));
+        self.inner.osm_preload(pairs)
+    }
+
+    fn osm_seal(&self, session_id: u64, sealed: &mut [u8], sealed_len: &mut usize) -> Result<(), BenchError> {
+        self.inner.osm_seal(session_id, sealed, sealed_len)
+    }
+
+    fn osm_unseal(&self, sealed: &[u8], server_ref: usize) -> Result<u64, BenchError> {
+        self.inner.osm_unseal(sealed, server_ref)
+    }
+
+    fn osm_search(&self, session_id: u64, key: Key, range: usize, values: &mut [Value]) -> Result<usize, BenchError> {
+        self.record(Some(TraceOp::Osm(Op::Search { key, offset: 0, range })));
+        self.inner.osm_search(session_id, key, range, values)
+    }
+
+    fn osm_insert_many(&self, session_id: u64, keys: &[Key], values: &[Value]) -> Result<(), BenchError> {
+        self.record(keys.iter().zip(values.iter()).map(|(&key, &value)| TraceOp::Osm(Op::Insert { key, value })));
+        self.inner.osm_insert_many(session_id, keys, values)
+    }
+
+    fn osm_insert_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError> {
+        self.record(Some(TraceOp::Osm(Op::Insert { key, value })));
+        self.inner.osm_insert_one(session_id, key, value)
+    }
+
+    fn osm_delete_one(&self, session_id: u64, key: Key, value: Value) -> Result<(), BenchError> {
+        self.record(Some(TraceOp::Osm(Op::Delete { key, value })));
+        self.inner.osm_delete_one(session_id, key, value)
+    }
+
+    fn osm_execute_batch(&self, session_id: u64, ops: &[u64], results: &mut [u64], cycles: &mut [u64]) -> Result<usize, BenchError> {
+        self.record(ops.chunks(OP_WORDS).filter(|op| op.len() == OP_WORDS).filter_map(Op::decode).map(TraceOp::Osm));
+        self.inner.osm_execute_batch(session_id, ops, results, cycles)
+    }
+
+    /// Recorded as the searches it makes: one per key, of range
+    /// `max_postings`.
+    fn osm_multi_search(
+        &self,
+        session_id: u64,
+        keys: &[Key],
+        conjunctive: bool,
+        max_postings: usize,
+        values: &mut [Value],
+        cycles: &mut u64,
+    ) -> Result<usize, BenchError> {
+        self.record(keys.iter().map(|&key| TraceOp::Osm(Op::Search { key, offset: 0, range: max_postings })));
+        self.inner.osm_multi_search(session_id, keys, conjunctive, max_postings, values, cycles)
+    }
+
+    fn oram_zerotrace(&self, client: &[u8], server_ref: usize, key_and_pos: &[u8], cycles: &mut [u64]) -> Result<(), BenchError> {
+        self.record_oram(key_and_pos);
+        self.inner.oram_zerotrace(client, server_ref, key_and_pos, cycles)
+    }
+
+    fn oram_access(&self, client: &[u8], server_ref: usize, key_and_pos: &[u8], block_size: usize, cycles: &mut [u64]) -> Result<(), BenchError> {
+        self.record_oram(key_and_pos);
+        self.inner.oram_access(client, server_ref, key_and_pos, block_size, cycles)
+    }
+}
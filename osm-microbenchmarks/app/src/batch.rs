@@ -0,0 +1,216 @@
+//! Encoding of the operations and results of `osm_execute_batch`.
+//!
+//! See `enclave/src/batch.rs` for the layout.
+
+use std::ops::Range;
+
+use errors::{BenchError, OsmStatus};
+use microbenchmarks::{Key, Value};
+use timing::{self, Latencies};
+
+pub const OP_WORDS: usize = 4;
+/// Most values a search may ask for. Ranges can come from trace files, and
+/// the host allocates the result buffer before the enclave sees them.
+pub const MAX_RANGE: usize = 1 << 20;
+/// Most result words a batch may need: 128 MiB of buffer.
+pub const MAX_RESULT_WORDS: usize = 1 << 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Search { key: Key, offset: usize, range: usize },
+    Insert { key: Key, value: Value },
+    Delete { key: Key, value: Value },
+    Update { key: Key, old: Value, new: Value },
+}
+
+/// The outcome of a single operation: the values it returned, or why it
+/// failed.
+pub type OpResult = Result<Vec<Value>, OsmStatus>;
+
+/// The results of a batch, the cycles the enclave spent on each operation,
+/// and the host time of the whole ECALL.
+pub struct BatchOutput {
+    pub results: Vec<OpResult>,
+    pub cycles: Vec<u64>,
+    pub host_secs: f64,
+}
+
+impl BatchOutput {
+    /// Adds the output of the batch that followed this one.
+    pub fn append(&mut self, next: BatchOutput) {
+        self.results.extend(next.results);
+        self.cycles.extend(next.cycles);
+        self.host_secs += next.host_secs;
+    }
+
+    /// The latency of every group of operations, such as the operations
+    /// that make up one query: the sum of their cycles. If the enclave did
+    /// not time them, the average host time of a group, as a single `Ecall`
+    /// sample; `microbenchmarks::execute_groups` avoids that.
+    pub fn group_latencies<I: IntoIterator<Item = Range<usize>>>(&self, groups: I) -> Latencies {
+        let groups: Vec<Range<usize>> = groups.into_iter().collect();
+        if !timing::is_timed(&self.cycles) {
+            return timing::untimed(self.host_secs, groups.len());
+        }
+        let secs = groups.into_iter()
+            .map(|group| timing::cycles_to_secs(self.cycles[group].iter().sum()))
+            .collect();
+        Latencies::enclave(secs, self.host_secs)
+    }
+}
+
+impl Op {
+    fn encode(&self) -> [u64; OP_WORDS] {
+        match *self {
+            Op::Search { key, offset, range } => [0, key, offset as u64, range as u64],
+            Op::Insert { key, value } => [1, key, value, 0],
+            Op::Delete { key, value } => [2, key, value, 0],
+            Op::Update { key, old, new } => [3, key, old, new],
+        }
+    }
+
+    /// Inverse of `encode`, for batches observed on their way into the
+    /// enclave.
+    pub fn decode(words: &[u64]) -> Option<Op> {
+        match words[0] {
+            0 => Some(Op::Search { key: words[1], offset: words[2] as usize, range: words[3] as usize }),
+            1 => Some(Op::Insert { key: words[1], value: words[2] }),
+            2 => Some(Op::Delete { key: words[1], value: words[2] }),
+            3 => Some(Op::Update { key: words[1], old: words[2], new: words[3] }),
+            _ => None,
+        }
+    }
+
+    /// Upper bound on the number of result words this operation produces,
+    /// or `None` if it does not fit in a `usize`.
+    pub fn result_words(&self) -> Option<usize> {
+        match *self {
+            Op::Search { range, .. } => range.checked_add(2),
+            _ => Some(2),
+        }
+    }
+}
+
+/// Every operation of `n` as a group of its own.
+pub fn singles(n: usize) -> Vec<Range<usize>> {
+    (0..n).map(|i| i..i + 1).collect()
+}
+
+/// `n_groups` runs of `group_len` consecutive operations.
+pub fn chunks(n_groups: usize, group_len: usize) -> Vec<Range<usize>> {
+    (0..n_groups).map(|i| i * group_len..(i + 1) * group_len).collect()
+}
+
+pub fn encode(ops: &[Op]) -> Vec<u64> {
+    let mut words = Vec::with_capacity(ops.len() * OP_WORDS);
+    for op in ops {
+        words.extend_from_slice(&op.encode());
+    }
+    words
+}
+
+/// The size of the result buffer needed to execute `ops`. Fails if it
+/// would exceed `MAX_RESULT_WORDS`.
+pub fn result_capacity(ops: &[Op]) -> Result<usize, BenchError> {
+    let mut total: usize = 0;
+    for op in ops {
+        total = op.result_words()
+            .and_then(|words| total.checked_add(words))
+            .and_then(|total| if total <= MAX_RESULT_WORDS { Some(total) } else { None })
+            .ok_or(BenchError::Osm(OsmStatus::InvalidInput))?;
+    }
+    Ok(total)
+}
+
+/// Decodes the results of a batch of `n_ops` operations.
+pub fn decode_results(words: &[u64], n_ops: usize) -> Result<Vec<OpResult>, BenchError> {
+    let malformed = BenchError::Osm(OsmStatus::InvalidInput);
+    let mut results = Vec::with_capacity(n_ops);
+    let mut i = 0;
+    while i < words.len() {
+        if i + 2 > words.len() {
+            return Err(malformed);
+        }
+        let (status, n) = (words[i], words[i + 1] as usize);
+        let end = n.checked_add(i + 2).ok_or(malformed)?;
+        let values = words.get(i + 2..end).ok_or(malformed)?;
+        results.push(match OsmStatus::from_code(status as u32) {
+            None => Ok(values.to_vec()),
+            Some(e) => Err(e),
+        });
+        i += 2 + n;
+    }
+    if results.len() == n_ops {
+        Ok(results)
+    } else {
+        Err(malformed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ops_round_trip() {
+        let ops = [
+            Op::Search { key: 1, offset: 2, range: 3 },
+            Op::Insert { key: 4, value: 5 },
+            Op::Delete { key: 6, value: 7 },
+            Op::Update { key: 8, old: 9, new: 10 },
+        ];
+        let words = encode(&ops);
+        assert_eq!(words.len(), ops.len() * OP_WORDS);
+        let decoded: Vec<Op> = words.chunks(OP_WORDS).map(|op| Op::decode(op).unwrap()).collect();
+        assert_eq!(decoded, ops);
+        assert_eq!(Op::decode(&[4, 0, 0, 0]), None);
+        assert_eq!(result_capacity(&ops), Ok(5 + 2 + 2 + 2));
+    }
+
+    #[test]
+    fn oversized_ranges_are_rejected() {
+        let search = |range| Op::Search { key: 1, offset: 0, range };
+        let invalid = Err(BenchError::Osm(OsmStatus::InvalidInput));
+        assert_eq!(result_capacity(&[search(MAX_RANGE)]), Ok(MAX_RANGE + 2));
+        assert_eq!(result_capacity(&[search(!0)]), invalid);
+        assert_eq!(result_capacity(&[search(MAX_RESULT_WORDS - 2), search(0)]), invalid);
+    }
+
+    #[test]
+    fn results_decode() {
+        // A search that found two values, a failed insert, a delete.
+        let words = [0, 2, 11, 12, 2, 0, 0, 0];
+        assert_eq!(
+            decode_results(&words, 3),
+            Ok(vec![Ok(vec![11, 12]), Err(OsmStatus::KeyNotFound), Ok(vec![])])
+        );
+    }
+
+    #[test]
+    fn malformed_results_are_rejected() {
+        let malformed = Err(BenchError::Osm(OsmStatus::InvalidInput));
+        // Fewer values than announced.
+        assert_eq!(decode_results(&[0, 3, 11, 12], 1), malformed);
+        // A count that would wrap around.
+        assert_eq!(decode_results(&[0, !0, 11], 1), malformed);
+        // A lone status word.
+        assert_eq!(decode_results(&[0, 0, 0], 2), malformed);
+        // More or fewer results than operations.
+        assert_eq!(decode_results(&[0, 0, 0, 0], 1), malformed);
+        assert_eq!(decode_results(&[0, 0], 2), malformed);
+    }
+
+    #[test]
+    fn untimed_batches_are_one_ecall_sample() {
+        let output = BatchOutput { results: Vec::new(), cycles: vec![timing::UNTIMED; 4], host_secs: 2.0 };
+        let latencies = output.group_latencies(chunks(2, 2));
+        assert_eq!(latencies.clock, timing::Clock::Ecall);
+        assert_eq!(latencies.secs, vec![1.0]);
+    }
+
+    #[test]
+    fn groups() {
+        assert_eq!(singles(3), vec![0..1, 1..2, 2..3]);
+        assert_eq!(chunks(2, 3), vec![0..3, 3..6]);
+    }
+}
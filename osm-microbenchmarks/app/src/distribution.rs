@@ -0,0 +1,211 @@
+//! Distributions of the keys that a benchmark queries.
+//!
+//! A distribution is chosen on the command line with `--distribution`:
+//!
+//! ```text
+//! repeat                  one key, drawn uniformly, for every query
+//! uniform                 every key equally likely
+//! zipf:<skew>             Zipfian over a random ranking of the keys, 0 < skew < 1
+//! hotspot:<keys>:<ops>    a fraction <ops> of the queries go to a fraction <keys> of the keys
+//! sequential              the keys in order, wrapping around
+//! fresh                   never the same key twice until every key has been queried
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+use serde::{Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    Repeat,
+    Uniform,
+    Zipfian { skew: f64 },
+    Hotspot { hot_keys: f64, hot_ops: f64 },
+    Sequential,
+    Fresh,
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let fraction = |part: &str| -> Result<f64, String> {
+            match part.parse::<f64>() {
+                Ok(x) if x > 0.0 && x < 1.0 => Ok(x),
+                _ => Err(format!("`{}` in `{}` must be strictly between 0 and 1", part, s)),
+            }
+        };
+        match parts.as_slice() {
+            ["repeat"] => Ok(Distribution::Repeat),
+            ["uniform"] => Ok(Distribution::Uniform),
+            ["zipf", skew] => Ok(Distribution::Zipfian { skew: fraction(skew)? }),
+            ["hotspot", keys, ops] => Ok(Distribution::Hotspot { hot_keys: fraction(keys)?, hot_ops: fraction(ops)? }),
+            ["sequential"] => Ok(Distribution::Sequential),
+            ["fresh"] => Ok(Distribution::Fresh),
+            _ => Err(format!(
+                "unknown distribution `{}` (expected repeat, uniform, zipf:<skew>, \
+                 hotspot:<keys>:<ops>, sequential or fresh)", s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Distribution::Repeat => write!(f, "repeat"),
+            Distribution::Uniform => write!(f, "uniform"),
+            Distribution::Zipfian { skew } => write!(f, "zipf:{}", skew),
+            Distribution::Hotspot { hot_keys, hot_ops } => write!(f, "hotspot:{}:{}", hot_keys, hot_ops),
+            Distribution::Sequential => write!(f, "sequential"),
+            Distribution::Fresh => write!(f, "fresh"),
+        }
+    }
+}
+
+impl Serialize for Distribution {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Distribution {
+    /// Draws `n_queries` indices into a set of `n_keys` keys.
+    pub fn sample<R: Rng>(&self, rng: &mut R, n_keys: usize, n_queries: usize) -> Vec<usize> {
+        assert!(n_keys > 0);
+        match *self {
+            Distribution::Repeat => vec![rng.gen_range(0, n_keys); n_queries],
+            Distribution::Uniform => (0..n_queries).map(|_| rng.gen_range(0, n_keys)).collect(),
+            Distribution::Zipfian { skew } => {
+                // Ranks are mapped to keys through a random permutation, so
+                // that popularity is unrelated to the order of the keys.
+                let ranking = permutation(rng, n_keys);
+                let zipf = Zipfian::new(n_keys, skew);
+                (0..n_queries).map(|_| ranking[zipf.next(rng)]).collect()
+            },
+            Distribution::Hotspot { hot_keys, hot_ops } => {
+                let ranking = permutation(rng, n_keys);
+                let n_hot = ((n_keys as f64 * hot_keys) as usize).max(1).min(n_keys);
+                (0..n_queries).map(|_| {
+                    if n_hot == n_keys || rng.gen::<f64>() < hot_ops {
+                        ranking[rng.gen_range(0, n_hot)]
+                    } else {
+                        ranking[rng.gen_range(n_hot, n_keys)]
+                    }
+                }).collect()
+            },
+            Distribution::Sequential => (0..n_queries).map(|i| i % n_keys).collect(),
+            Distribution::Fresh => {
+                let mut indices = Vec::with_capacity(n_queries);
+                while indices.len() < n_queries {
+                    let round = permutation(rng, n_keys);
+                    let take = (n_queries - indices.len()).min(n_keys);
+                    indices.extend_from_slice(&round[..take]);
+                }
+                indices
+            },
+        }
+    }
+}
+
+fn permutation<R: Rng>(rng: &mut R, n: usize) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..n).collect();
+    rng.shuffle(&mut indices);
+    indices
+}
+
+/// Zipfian ranks in `0..n`, using the generator of Gray et al., "Quickly
+/// Generating Billion-Record Synthetic Databases" (as in YCSB). Rank 0 is
+/// the most popular.
+pub struct Zipfian {
+    n: usize,
+    theta: f64,
+    zeta_n: f64,
+    alpha: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    /// `theta` must be strictly between 0 and 1.
+    pub fn new(n: usize, theta: f64) -> Zipfian {
+        let zeta = |n: usize| (1..n + 1).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zeta_n = zeta(n);
+        Zipfian {
+            n,
+            theta,
+            zeta_n,
+            alpha: 1.0 / (1.0 - theta),
+            eta: (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta(2) / zeta_n),
+        }
+    }
+
+    pub fn next<R: Rng>(&self, rng: &mut R) -> usize {
+        let u = rng.gen::<f64>();
+        let uz = u * self.zeta_n;
+        let rank = if uz < 1.0 {
+            0
+        } else if uz < 1.0 + 0.5f64.powf(self.theta) {
+            1
+        } else {
+            (self.n as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as usize
+        };
+        rank.min(self.n - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use microbenchmarks::workload_rng;
+
+    const ALL: [Distribution; 6] = [
+        Distribution::Repeat,
+        Distribution::Uniform,
+        Distribution::Zipfian { skew: 0.99 },
+        Distribution::Hotspot { hot_keys: 0.2, hot_ops: 0.8 },
+        Distribution::Sequential,
+        Distribution::Fresh,
+    ];
+
+    #[test]
+    fn names_round_trip() {
+        for d in ALL.iter() {
+            assert_eq!(d.to_string().parse::<Distribution>(), Ok(*d));
+        }
+        for bad in ["zipf", "zipf:1", "zipf:0", "hotspot:0.2", "hotspot:0.2:1.5", "gaussian"].iter() {
+            assert!(bad.parse::<Distribution>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn samples_are_in_range_and_seeded() {
+        for d in ALL.iter() {
+            let indices = d.sample(&mut workload_rng(1), 50, 1000);
+            assert_eq!(indices.len(), 1000);
+            assert!(indices.iter().all(|&i| i < 50), "{}", d);
+            assert_eq!(indices, d.sample(&mut workload_rng(1), 50, 1000), "{}", d);
+        }
+    }
+
+    #[test]
+    fn fresh_covers_every_key_before_repeating() {
+        let mut first_round = Distribution::Fresh.sample(&mut workload_rng(1), 50, 120)[..50].to_vec();
+        first_round.sort();
+        assert_eq!(first_round, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn zipfian_favors_low_ranks() {
+        let zipf = Zipfian::new(100, 0.99);
+        let mut rng = workload_rng(1);
+        let mut counts = vec![0; 100];
+        for _ in 0..10000 {
+            counts[zipf.next(&mut rng)] += 1;
+        }
+        assert!(counts[0] > counts[1]);
+        assert!(counts[1] > counts[50]);
+    }
+}
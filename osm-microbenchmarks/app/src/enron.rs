@@ -0,0 +1,304 @@
+//! Searchable encryption on the Enron email corpus: an inverted index from
+//! keywords to the ids of the documents that contain them, stored in OSM.
+//!
+//! The index is a JSON object mapping every keyword to its posting list
+//! (as written by `build-index`):
+//!
+//! ```text
+//! { "energy": [3, 17, 242], "gas": [17, 99], ... }
+//! ```
+//!
+//! Keywords are stored under a 64-bit hash; a search for a keyword returns
+//! the first document ids of its posting list in OSM order.
+//!
+//! Multi-keyword queries (AND/OR) go through `osm_multi_search`, which
+//! fetches every posting list with the range of the longest one in the
+//! index, pads them to that length and merges them obliviously inside the
+//! enclave. Besides the size of the final result, what the host can still
+//! learn is the length of each list as the OSM client returns it: see
+//! `enclave/src/oblivious.rs`.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+
+use generic_array::typenum::U160;
+use osm::STDOsmClient;
+use path_oram::{LocalServer, PathDOramClient};
+use rand::{self, Rng};
+use serde_json;
+use time;
+
+use backend::EnclaveBackend;
+use batch::{self, Op};
+use distribution::Distribution;
+use errors::{BenchError, OsmStatus};
+use microbenchmarks::{self, Key, Value, WorkloadRng};
+use timing::{self, Latencies};
+
+/// Number of measurements every benchmark averages over.
+pub const MEASUREMENTS: usize = 100;
+
+/// The key a keyword is stored under.
+pub fn keyword_key(keyword: &str) -> Key {
+    microbenchmarks::hash_key(keyword.as_bytes())
+}
+
+/// How the keywords of a multi-keyword query are combined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connective {
+    /// Documents that contain every keyword.
+    And,
+    /// Documents that contain any of the keywords.
+    Or,
+}
+
+impl fmt::Display for Connective {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+pub struct Index {
+    /// Posting lists by keyword key, sorted and free of duplicates.
+    pub postings: BTreeMap<Key, Vec<Value>>,
+}
+
+impl Index {
+    pub fn load(path: &str) -> Result<Index, BenchError> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        let by_keyword: BTreeMap<String, Vec<Value>> = serde_json::from_reader(file)
+            .map_err(|e| {
+                progress!("[-] {}: {}", path, e);
+                BenchError::Io(io::ErrorKind::InvalidData)
+            })?;
+        let mut postings: BTreeMap<Key, BTreeSet<Value>> = BTreeMap::new();
+        for (keyword, documents) in by_keyword {
+            postings.entry(keyword_key(&keyword)).or_insert_with(BTreeSet::new).extend(documents);
+        }
+        let index = Index {
+            postings: postings.into_iter()
+                .filter(|&(_, ref documents)| !documents.is_empty())
+                .map(|(key, documents)| (key, documents.into_iter().collect()))
+                .collect(),
+        };
+        progress!(
+            "[+] Loaded {}: {} keywords, {} postings, {} documents",
+            path, index.postings.len(), index.n_postings(), index.documents().len()
+        );
+        Ok(index)
+    }
+
+    pub fn n_postings(&self) -> usize {
+        self.postings.values().map(|documents| documents.len()).sum()
+    }
+
+    pub fn max_posting_len(&self) -> usize {
+        self.postings.values().map(|documents| documents.len()).max().unwrap_or(0)
+    }
+
+    /// The documents matching a multi-keyword query, in ascending order.
+    fn matching(&self, keys: &[Key], connective: Connective) -> Vec<Value> {
+        let mut lists = keys.iter().map(|key| self.postings[key].iter().cloned().collect::<BTreeSet<Value>>());
+        let first = lists.next().unwrap_or_default();
+        let matching = lists.fold(first, |acc, list| match connective {
+            Connective::And => acc.intersection(&list).cloned().collect(),
+            Connective::Or => acc.union(&list).cloned().collect(),
+        });
+        matching.into_iter().collect()
+    }
+
+    /// The keywords of every document.
+    pub fn documents(&self) -> BTreeMap<Value, Vec<Key>> {
+        let mut documents = BTreeMap::new();
+        for (&key, ids) in self.postings.iter() {
+            for &id in ids {
+                documents.entry(id).or_insert_with(Vec::new).push(key);
+            }
+        }
+        documents
+    }
+
+    /// Every (keyword, document) pair.
+    pub fn pairs(&self) -> Vec<(Key, Value)> {
+        let mut pairs = Vec::with_capacity(self.n_postings());
+        for (&key, ids) in self.postings.iter() {
+            pairs.extend(ids.iter().map(|&id| (key, id)));
+        }
+        pairs
+    }
+
+    /// Sets up an OSM holding the index, with room for `extra` more
+    /// postings.
+    pub fn setup<B: EnclaveBackend>(
+        &self,
+        backend: &B,
+        extra: usize,
+    ) -> (STDOsmClient<Key, Value, PathDOramClient<U160>>, LocalServer<PathDOramClient<U160>>) {
+        let pairs = self.pairs();
+        let capacity = pairs.len() + extra;
+        let setup = microbenchmarks::setup_osm(backend, capacity, pairs);
+        progress!("[+] Done with setup");
+        setup
+    }
+}
+
+/// Runs `MEASUREMENTS` keyword searches, each returning the first
+/// `n_results` documents of a keyword drawn from `distribution`.
+pub fn search<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    distribution: Distribution,
+    index: &Index,
+    n_results: usize,
+) -> Result<Latencies, BenchError> {
+    progress!("\n[+] Keywords: {}, Results: {}", index.postings.len(), n_results);
+    if index.postings.is_empty() {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+    let keys: Vec<Key> = index.postings.keys().cloned().collect();
+    let queries: Vec<Key> = distribution.sample(rng, keys.len(), MEASUREMENTS)
+        .into_iter()
+        .map(|i| keys[i])
+        .collect();
+
+    let (osm_client, mut server) = index.setup(backend, 0);
+    let session_id = microbenchmarks::open_session(backend, &osm_client, &mut server)?;
+    let ops: Vec<Op> = queries.iter().map(|&key| Op::Search { key, offset: 0, range: n_results }).collect();
+    let executed = microbenchmarks::execute_groups(backend, session_id, &ops, &batch::singles(ops.len()));
+    backend.osm_close(session_id)?;
+    let (results, latencies) = executed?;
+
+    for (result, key) in results.iter().zip(queries.iter()) {
+        let documents = result.clone().map_err(BenchError::Osm)?;
+        let expected: HashSet<Value> = index.postings[key].iter().cloned().collect();
+        microbenchmarks::verify_search(&documents, &expected, n_results)?;
+    }
+    progress!("[+] Searches: {}, avg. time (s): {}", MEASUREMENTS, latencies.mean());
+    Ok(latencies)
+}
+
+/// Runs `MEASUREMENTS` insertions of `n_documents` new documents each. A
+/// new document takes the keywords of a random existing one, so that
+/// document lengths follow the corpus; inserting it inserts one posting
+/// per keyword.
+pub fn insert<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    index: &Index,
+    n_documents: usize,
+) -> Result<Latencies, BenchError> {
+    progress!("\n[+] Keywords: {}, Documents per insertion: {}", index.postings.len(), n_documents);
+    let documents: Vec<(Value, Vec<Key>)> = index.documents().into_iter().collect();
+    if documents.is_empty() {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+    let mut next_id = documents[documents.len() - 1].0 + 1;
+
+    let mut ops = Vec::new();
+    // Where each measurement starts in `ops`.
+    let mut starts = Vec::with_capacity(MEASUREMENTS + 1);
+    for _ in 0..MEASUREMENTS {
+        starts.push(ops.len());
+        for _ in 0..n_documents {
+            let &(_, ref keywords) = rng.choose(&documents).unwrap();
+            ops.extend(keywords.iter().map(|&key| Op::Insert { key, value: next_id }));
+            next_id += 1;
+        }
+    }
+    starts.push(ops.len());
+
+    let (osm_client, mut server) = index.setup(backend, ops.len());
+    let session_id = microbenchmarks::open_session(backend, &osm_client, &mut server)?;
+    let groups: Vec<Range<usize>> = starts.windows(2).map(|w| w[0]..w[1]).collect();
+    let executed = microbenchmarks::execute_groups(backend, session_id, &ops, &groups);
+    backend.osm_close(session_id)?;
+    let (results, latencies) = executed?;
+    for result in results.iter() {
+        result.clone().map_err(BenchError::Osm)?;
+    }
+
+    let postings_per_document = ops.len() as f64 / (MEASUREMENTS * n_documents.max(1)) as f64;
+    progress!(
+        "[+] Insertions: {}, postings per document: {:.1}, avg. time (s): {}",
+        MEASUREMENTS, postings_per_document, latencies.mean()
+    );
+    Ok(latencies)
+}
+
+/// Runs `MEASUREMENTS` queries of `n_terms` keywords joined by
+/// `connective`. The keywords of a query are those of a random document
+/// with at least `n_terms` of them, so that conjunctions usually match.
+pub fn multi_search<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    index: &Index,
+    connective: Connective,
+    n_terms: usize,
+) -> Result<Latencies, BenchError> {
+    let max_postings = index.max_posting_len();
+    progress!(
+        "\n[+] Keywords: {}, Query: {} terms joined by {}, padded posting length: {}",
+        index.postings.len(), n_terms, connective, max_postings
+    );
+    let documents: Vec<Vec<Key>> = index.documents().into_iter()
+        .map(|(_, keys)| keys)
+        .filter(|keys| keys.len() >= n_terms)
+        .collect();
+    if n_terms == 0 || documents.is_empty() {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+    let mut queries = Vec::with_capacity(MEASUREMENTS);
+    for _ in 0..MEASUREMENTS {
+        let keys = rng.choose(&documents).unwrap();
+        queries.push(rand::sample(&mut *rng, keys.iter().cloned(), n_terms));
+    }
+
+    let conjunctive = connective == Connective::And;
+    let mut values = vec![0; if conjunctive { max_postings } else { n_terms * max_postings }];
+    let (osm_client, mut server) = index.setup(backend, 0);
+    let session_id = microbenchmarks::open_session(backend, &osm_client, &mut server)?;
+    let mut cycles = Vec::with_capacity(MEASUREMENTS);
+    let mut host_secs = Vec::with_capacity(MEASUREMENTS);
+    let mut results = Vec::with_capacity(MEASUREMENTS);
+    let mut result = Ok(());
+    for keys in queries.iter() {
+        let mut query_cycles = 0;
+        let start = time::precise_time_s();
+        let searched = backend.osm_multi_search(session_id, keys, conjunctive, max_postings, &mut values, &mut query_cycles);
+        host_secs.push(time::precise_time_s() - start);
+        match searched {
+            Ok(count) => {
+                results.push(values[..count].to_vec());
+                cycles.push(query_cycles);
+            },
+            Err(e) => {
+                result = Err(e);
+                break;
+            },
+        }
+    }
+    backend.osm_close(session_id)?;
+    result?;
+
+    for (keys, documents) in queries.iter().zip(results.iter()) {
+        if *documents != index.matching(keys, connective) {
+            return Err(BenchError::IncorrectResult);
+        }
+    }
+    // Every query is its own ECALL, so without enclave timing the host
+    // times are per query too.
+    let latencies = if timing::is_timed(&cycles) {
+        Latencies::enclave(timing::secs(&cycles), host_secs.iter().sum())
+    } else {
+        Latencies::host(host_secs)
+    };
+    let mean_results = results.iter().map(|r| r.len()).sum::<usize>() as f64 / MEASUREMENTS as f64;
+    progress!(
+        "[+] Queries: {}, avg. results: {:.1}, avg. time (s): {}",
+        MEASUREMENTS, mean_results, latencies.mean()
+    );
+    Ok(latencies)
+}
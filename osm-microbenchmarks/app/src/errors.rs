@@ -0,0 +1,95 @@
+//! Errors reported by the benchmarks.
+//!
+//! An ECALL can fail in two ways: the SGX runtime can fail to enter the
+//! enclave (reported through the ECALL's `sgx_status_t`), or the operation
+//! inside the enclave can fail (reported through `retval` as an
+//! `OsmStatus`, mirrored from `enclave/src/error.rs`).
+
+use std::fmt;
+use std::io;
+
+use sgx_types::sgx_status_t;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OsmStatus {
+    StashOverflow,
+    KeyNotFound,
+    IntegrityFailure,
+    CapacityExceeded,
+    InvalidSession,
+    InvalidInput,
+    BufferTooSmall,
+    Unknown(u32),
+}
+
+impl OsmStatus {
+    /// Decodes an ECALL return value; `None` means success.
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => None,
+            1 => Some(OsmStatus::StashOverflow),
+            2 => Some(OsmStatus::KeyNotFound),
+            3 => Some(OsmStatus::IntegrityFailure),
+            4 => Some(OsmStatus::CapacityExceeded),
+            5 => Some(OsmStatus::InvalidSession),
+            6 => Some(OsmStatus::InvalidInput),
+            7 => Some(OsmStatus::BufferTooSmall),
+            c => Some(OsmStatus::Unknown(c)),
+        }
+    }
+}
+
+impl fmt::Display for OsmStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OsmStatus::StashOverflow => write!(f, "stash overflow"),
+            OsmStatus::KeyNotFound => write!(f, "key not found"),
+            OsmStatus::IntegrityFailure => write!(f, "integrity check failed"),
+            OsmStatus::CapacityExceeded => write!(f, "capacity exceeded"),
+            OsmStatus::InvalidSession => write!(f, "invalid session"),
+            OsmStatus::InvalidInput => write!(f, "invalid input"),
+            OsmStatus::BufferTooSmall => write!(f, "output buffer too small"),
+            OsmStatus::Unknown(c) => write!(f, "unknown error ({})", c),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BenchError {
+    Sgx(sgx_status_t),
+    Osm(OsmStatus),
+    /// The enclave returned values that do not match the dataset.
+    IncorrectResult,
+    Io(io::ErrorKind),
+    /// A benchmark thread panicked.
+    ThreadPanicked,
+}
+
+impl From<io::Error> for BenchError {
+    fn from(e: io::Error) -> Self {
+        BenchError::Io(e.kind())
+    }
+}
+
+impl fmt::Display for BenchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BenchError::Sgx(e) => write!(f, "ECALL failed: {}", e.as_str()),
+            BenchError::Osm(e) => write!(f, "operation failed: {}", e),
+            BenchError::IncorrectResult => write!(f, "incorrect result"),
+            BenchError::Io(e) => write!(f, "I/O error: {:?}", e),
+            BenchError::ThreadPanicked => write!(f, "a benchmark thread panicked"),
+        }
+    }
+}
+
+/// Combines the transport status and the return value of an ECALL.
+pub fn check(result: sgx_status_t, retval: u32) -> Result<(), BenchError> {
+    match result {
+        sgx_status_t::SGX_SUCCESS => match OsmStatus::from_code(retval) {
+            None => Ok(()),
+            Some(e) => Err(BenchError::Osm(e)),
+        },
+        e => Err(BenchError::Sgx(e)),
+    }
+}
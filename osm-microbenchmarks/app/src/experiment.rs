@@ -0,0 +1,331 @@
+//! Declarative parameter sweeps, run with `run <experiment.toml>`.
+//!
+//! An experiment names a benchmark, the values of each parameter to sweep,
+//! and how often to repeat each point. The sweep is the cartesian product
+//! of the listed values; parameters that are not listed keep the
+//! benchmark's default. Files ending in `.json` are read as JSON, anything
+//! else as TOML:
+//!
+//! ```toml
+//! benchmark = "osm::range"
+//! repetitions = 5
+//! output = "results/range.csv"
+//! format = "csv"
+//!
+//! [grid]
+//! n_keys = [65536, 131072]
+//! vals_per_key = [16, 256]
+//! range = [1, 10, 60]
+//! distribution = ["repeat", "zipf:0.99"]
+//! ```
+//!
+//! Benchmarks and the parameters they take:
+//!
+//! ```text
+//! osm::range               n_keys, vals_per_key, range, distribution
+//! osm::insert_many         n_keys, n_ops
+//! osm::insert_one          n_keys, n_ops
+//! osm::delete_one          n_keys, n_ops
+//! osm::mixed               n_keys, n_ops
+//! osm::throughput          n_keys, n_ops, n_threads
+//! osm::ycsb_{a,...,f}      n_keys, n_ops, vals_per_key
+//! oram::zerotrace          n_keys, distribution
+//! oram::access             n_keys, block_size, distribution
+//! signal::discovery        n_keys, n_ops (contacts per batch)
+//! kt::lookup               n_keys
+//! kt::update               n_keys
+//! ```
+//!
+//! The other benchmarks cannot be swept this way, and an experiment naming
+//! one is rejected: `se::*` needs an index, `osm::save`, `osm::resume` and
+//! the replays read or write files, and `setup::*` reports a benchmark per
+//! stage. Their subcommands run them.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json;
+use toml;
+
+use backend::EnclaveBackend;
+use distribution::Distribution;
+use errors::BenchError;
+use key_transparency;
+use microbenchmarks::{self, WorkloadRng};
+use report::{OutputFormat, Params};
+use signal;
+use timing::Latencies;
+use ycsb::{self, Workload};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    pub benchmark: String,
+    #[serde(default = "one")]
+    pub repetitions: usize,
+    /// Where to write the results; stdout if omitted.
+    pub output: Option<String>,
+    /// `text`, `json` or `csv`; the `--output` option if omitted.
+    pub format: Option<String>,
+    #[serde(default)]
+    pub grid: Grid,
+}
+
+fn one() -> usize {
+    1
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Grid {
+    pub n_keys: Vec<usize>,
+    pub vals_per_key: Vec<usize>,
+    pub range: Vec<usize>,
+    pub block_size: Vec<usize>,
+    pub n_ops: Vec<usize>,
+    pub n_threads: Vec<usize>,
+    pub distribution: Vec<String>,
+}
+
+impl Experiment {
+    pub fn load(path: &str) -> Result<Experiment, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let experiment: Experiment = if Path::new(path).extension().map_or(false, |ext| ext == "json") {
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?
+        } else {
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?
+        };
+        if experiment.repetitions == 0 {
+            return Err(format!("{}: repetitions must be at least 1", path));
+        }
+        if let Some(reason) = unsupported(&experiment.benchmark) {
+            return Err(format!("{}: `{}` cannot be run from an experiment: {}", path, experiment.benchmark, reason));
+        }
+        if !is_known(&experiment.benchmark) {
+            return Err(format!("{}: unknown benchmark `{}`", path, experiment.benchmark));
+        }
+        Ok(experiment)
+    }
+
+    pub fn format(&self) -> Result<Option<OutputFormat>, String> {
+        match self.format {
+            Some(ref format) => format.parse().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Expands the grid into every combination of its values.
+    pub fn points(&self) -> Result<Vec<Params>, String> {
+        let distributions = self.grid.distribution.iter()
+            .map(|d| d.parse::<Distribution>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        fn axis<T: Copy>(values: &[T]) -> Vec<Option<T>> {
+            if values.is_empty() { vec![None] } else { values.iter().map(|&v| Some(v)).collect() }
+        }
+
+        let mut points = vec![Params::default()];
+        macro_rules! expand {
+            ($field:ident, $values:expr) => {
+                points = points.iter()
+                    .flat_map(|p| axis($values).into_iter().map(move |v| Params { $field: v, ..*p }))
+                    .collect();
+            }
+        }
+        expand!(n_keys, &self.grid.n_keys);
+        expand!(vals_per_key, &self.grid.vals_per_key);
+        expand!(range, &self.grid.range);
+        expand!(block_size, &self.grid.block_size);
+        expand!(n_ops, &self.grid.n_ops);
+        expand!(n_threads, &self.grid.n_threads);
+        expand!(distribution, &distributions);
+        Ok(points)
+    }
+}
+
+fn ycsb_workload(benchmark: &str) -> Option<Workload> {
+    if benchmark.starts_with("osm::ycsb_") {
+        benchmark["osm::ycsb_".len()..].parse().ok()
+    } else {
+        None
+    }
+}
+
+fn is_known(benchmark: &str) -> bool {
+    match benchmark {
+        "osm::range" | "osm::insert_many" | "osm::insert_one" | "osm::delete_one" | "osm::mixed"
+        | "osm::throughput" | "oram::zerotrace" | "oram::access" | "signal::discovery" | "kt::lookup"
+        | "kt::update" => true,
+        _ => ycsb_workload(benchmark).is_some(),
+    }
+}
+
+/// Why a benchmark that exists cannot be swept, if it cannot.
+fn unsupported(benchmark: &str) -> Option<&'static str> {
+    match benchmark {
+        "osm::save" | "osm::resume" => Some("it needs a saved storage; use the `osm` subcommand"),
+        "osm::replay" | "oram::replay" => Some("it needs a trace; use the `osm` or `oram` subcommand"),
+        "setup::osm" | "setup::oram" => Some("it reports each stage as a benchmark; use the `setup` subcommand"),
+        _ if benchmark.starts_with("se::") => Some("it needs an Enron index; use the `se` subcommand"),
+        _ => None,
+    }
+}
+
+/// Fills in the parameters that a point leaves unset with the same
+/// defaults as the corresponding subcommand.
+pub fn with_defaults(benchmark: &str, mut p: Params) -> Params {
+    p.n_keys = p.n_keys.or(Some(match benchmark {
+        "signal::discovery" => signal::MIN_DIRECTORY_SIZE,
+        "kt::lookup" | "kt::update" => key_transparency::MIN_DIRECTORY_SIZE,
+        _ => 1024,
+    }));
+    match benchmark {
+        "osm::range" => {
+            p.vals_per_key = p.vals_per_key.or(Some(16));
+            p.range = p.range.or(Some(10));
+            p.distribution = p.distribution.or(Some(Distribution::Repeat));
+        },
+        "osm::insert_many" => p.n_ops = p.n_ops.or(Some(100)),
+        "osm::insert_one" | "osm::delete_one" => p.n_ops = p.n_ops.or(Some(1)),
+        "osm::mixed" => p.n_ops = p.n_ops.or(Some(1000)),
+        "osm::throughput" => {
+            p.n_ops = p.n_ops.or(Some(1000));
+            p.n_threads = p.n_threads.or(Some(4));
+        },
+        "oram::zerotrace" => {
+            p.block_size = Some(8);
+            p.distribution = p.distribution.or(Some(Distribution::Uniform));
+        },
+        "oram::access" => {
+            p.block_size = p.block_size.or(Some(160));
+            p.distribution = p.distribution.or(Some(Distribution::Uniform));
+        },
+        "signal::discovery" => p.n_ops = p.n_ops.or(Some(signal::BATCH_SIZES[0])),
+        "kt::lookup" | "kt::update" => {},
+        _ => {
+            p.n_ops = p.n_ops.or(Some(1000));
+            p.vals_per_key = p.vals_per_key.or(Some(10));
+        },
+    }
+    p
+}
+
+/// Runs one point of the sweep. `p` must have been through
+/// `with_defaults`.
+pub fn run_point<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    benchmark: &str,
+    p: &Params,
+) -> Result<Latencies, BenchError> {
+    let n_keys = p.n_keys.unwrap();
+    match benchmark {
+        "osm::range" => microbenchmarks::search(
+            backend, rng, p.distribution.unwrap(), n_keys, p.vals_per_key.unwrap(), p.range.unwrap()
+        ),
+        "osm::insert_many" => microbenchmarks::insert_many(backend, rng, n_keys, p.n_ops.unwrap()),
+        "osm::insert_one" => microbenchmarks::insert_one(backend, rng, n_keys, p.n_ops.unwrap()),
+        "osm::delete_one" => microbenchmarks::delete_one(backend, rng, n_keys, p.n_ops.unwrap()),
+        "osm::mixed" => microbenchmarks::mixed(backend, rng, n_keys, p.n_ops.unwrap()),
+        "osm::throughput" => microbenchmarks::throughput(backend, rng, p.n_threads.unwrap(), n_keys, p.n_ops.unwrap()),
+        "oram::zerotrace" => microbenchmarks::zerotrace(backend, rng, p.distribution.unwrap(), n_keys),
+        "oram::access" => microbenchmarks::doram(
+            backend, rng, p.distribution.unwrap(), n_keys, p.block_size.unwrap() as u64
+        ),
+        "signal::discovery" => signal::run(backend, rng, n_keys, p.n_ops.unwrap()),
+        "kt::lookup" => key_transparency::lookup(backend, rng, n_keys),
+        "kt::update" => key_transparency::update(backend, rng, n_keys),
+        _ => {
+            // `Experiment::load` only accepts known benchmarks.
+            let workload = ycsb_workload(benchmark).unwrap();
+            ycsb::run(backend, rng, workload, n_keys, p.n_ops.unwrap(), p.vals_per_key.unwrap())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn experiment(text: &str) -> Experiment {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn points_are_the_cartesian_product() {
+        let e = experiment(r#"
+            benchmark = "osm::range"
+            [grid]
+            n_keys = [1, 2]
+            range = [10, 20, 30]
+            distribution = ["repeat", "zipf:0.5"]
+        "#);
+        let points = e.points().unwrap();
+        assert_eq!(points.len(), 2 * 3 * 2);
+        for &n_keys in [1, 2].iter() {
+            for &range in [10, 20, 30].iter() {
+                for &distribution in [Distribution::Repeat, Distribution::Zipfian { skew: 0.5 }].iter() {
+                    assert_eq!(points.iter().filter(|p| {
+                        p.n_keys == Some(n_keys) && p.range == Some(range) && p.distribution == Some(distribution)
+                    }).count(), 1);
+                }
+            }
+        }
+        assert!(points.iter().all(|p| p.vals_per_key.is_none() && p.n_ops.is_none()));
+    }
+
+    #[test]
+    fn empty_grid_is_one_point() {
+        let points = experiment(r#"benchmark = "osm::mixed""#).points().unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].n_keys, None);
+    }
+
+    #[test]
+    fn bad_distributions_are_rejected() {
+        let e = experiment(r#"
+            benchmark = "osm::range"
+            [grid]
+            distribution = ["zipf:2"]
+        "#);
+        assert!(e.points().is_err());
+    }
+
+    #[test]
+    fn defaults_fill_only_unset_parameters() {
+        let p = with_defaults("osm::range", Params { range: Some(1), ..Params::default() });
+        assert_eq!(p.n_keys, Some(1024));
+        assert_eq!(p.vals_per_key, Some(16));
+        assert_eq!(p.range, Some(1));
+        assert_eq!(p.distribution, Some(Distribution::Repeat));
+
+        // ZeroTrace only supports 8-byte blocks.
+        let p = with_defaults("oram::zerotrace", Params { block_size: Some(64), ..Params::default() });
+        assert_eq!(p.block_size, Some(8));
+
+        let p = with_defaults("osm::ycsb_a", Params::default());
+        assert_eq!((p.n_ops, p.vals_per_key), (Some(1000), Some(10)));
+    }
+
+    #[test]
+    fn benchmarks_are_checked() {
+        assert!(is_known("osm::throughput"));
+        assert!(is_known("osm::ycsb_f"));
+        assert!(!is_known("osm::ycsb_z"));
+        assert!(!is_known("osm::scan"));
+        assert!(is_known("signal::discovery"));
+        assert!(is_known("kt::update"));
+        for benchmark in ["se::search", "se::and", "setup::osm", "setup::oram", "osm::replay", "osm::resume"].iter() {
+            assert!(unsupported(benchmark).is_some(), "{}", benchmark);
+        }
+        assert!(unsupported("osm::range").is_none());
+    }
+
+    #[test]
+    fn application_defaults_match_their_subcommands() {
+        let p = with_defaults("signal::discovery", Params::default());
+        assert_eq!((p.n_keys, p.n_ops), (Some(signal::MIN_DIRECTORY_SIZE), Some(1)));
+        let p = with_defaults("kt::lookup", Params { n_keys: Some(20000), ..Params::default() });
+        assert_eq!((p.n_keys, p.n_ops), (Some(20000), None));
+    }
+}
@@ -0,0 +1,309 @@
+//! Key transparency with an oblivious key directory.
+//!
+//! Users are numbered `0..n`. Every key a user has published is stored in
+//! OSM under the user's id, as a value that packs the key version and a
+//! 48-bit stand-in for the public key. Newer versions sort first, so
+//! `search(user, 0, 1)` returns the current key. An update inserts a new
+//! version and keeps the old ones.
+//!
+//! A Merkle tree over the current bindings (leaf `i` is user `i`) lets
+//! clients check that everyone sees the same directory. Its nodes live in
+//! the same OSM, under `NODE_TAG | i` for heap index `i` (the root is 1),
+//! so fetching an authentication path is as oblivious as fetching a key:
+//!
+//! ```text
+//! lookup   search(user) + one search per level for the sibling hashes
+//! update   insert(user, new version) + an update of every node on the
+//!          path from the user's leaf to the root
+//! ```
+//!
+//! Hashes are 64-bit FNV-1a, standing in for a cryptographic hash; the
+//! benchmark measures the accesses, not the hashing.
+
+use rand::Rng;
+
+use backend::EnclaveBackend;
+use batch::{self, Op};
+use errors::{BenchError, OsmStatus};
+use microbenchmarks::{self, Key, Value, WorkloadRng};
+use timing::Latencies;
+
+/// Number of lookups or updates every point averages over.
+pub const MEASUREMENTS: usize = 100;
+/// Directory sizes are halved down to this one.
+pub const MIN_DIRECTORY_SIZE: usize = 10000;
+
+/// Set in the keys of Merkle tree nodes, which user ids never reach.
+const NODE_TAG: Key = 1 << 63;
+const KEY_BITS: u32 = 48;
+const KEY_MASK: u64 = (1 << KEY_BITS) - 1;
+const MAX_VERSION: u64 = (1 << (64 - KEY_BITS)) - 1;
+
+fn binding(version: u64, public_key: u64) -> Value {
+    ((MAX_VERSION - version) << KEY_BITS) | (public_key & KEY_MASK)
+}
+
+/// The version and public key in a binding.
+fn unpack(value: Value) -> (u64, u64) {
+    (MAX_VERSION - (value >> KEY_BITS), value & KEY_MASK)
+}
+
+fn node_key(index: usize) -> Key {
+    NODE_TAG | index as Key
+}
+
+fn hash_words(words: &[u64]) -> u64 {
+    let bytes: Vec<u8> = words.iter().flat_map(|&w| (0..8).map(move |i| (w >> (8 * i)) as u8)).collect();
+    microbenchmarks::hash_key(&bytes)
+}
+
+fn leaf_hash(user: usize, value: Value) -> u64 {
+    hash_words(&[0, user as u64, value])
+}
+
+fn node_hash(left: u64, right: u64) -> u64 {
+    hash_words(&[1, left, right])
+}
+
+/// Recomputes the root from a user's binding and the sibling hashes on the
+/// way up from its leaf.
+fn root_from_path(user: usize, n_leaves: usize, value: Value, path: &[u64]) -> u64 {
+    let mut index = n_leaves + user;
+    let mut hash = leaf_hash(user, value);
+    for &sibling in path {
+        hash = if index % 2 == 0 { node_hash(hash, sibling) } else { node_hash(sibling, hash) };
+        index /= 2;
+    }
+    hash
+}
+
+/// The directory as the key server's model of it: what OSM holds after the
+/// operations generated so far.
+struct Directory {
+    n_leaves: usize,
+    /// The current binding of every user.
+    bindings: Vec<Value>,
+    /// The Merkle tree in heap order; empty leaves hash to 0.
+    nodes: Vec<u64>,
+}
+
+impl Directory {
+    fn new(rng: &mut WorkloadRng, n_users: usize) -> Directory {
+        let n_leaves = n_users.next_power_of_two();
+        let bindings: Vec<Value> = (0..n_users).map(|_| binding(0, rng.gen())).collect();
+        let mut nodes = vec![0; 2 * n_leaves];
+        for (user, &value) in bindings.iter().enumerate() {
+            nodes[n_leaves + user] = leaf_hash(user, value);
+        }
+        for i in (1..n_leaves).rev() {
+            nodes[i] = node_hash(nodes[2 * i], nodes[2 * i + 1]);
+        }
+        Directory { n_leaves, bindings, nodes }
+    }
+
+    fn root(&self) -> u64 {
+        self.nodes[1]
+    }
+
+    /// Heap indices of the siblings on the way up from a user's leaf.
+    fn siblings(&self, user: usize) -> Vec<usize> {
+        let mut siblings = Vec::new();
+        let mut index = self.n_leaves + user;
+        while index > 1 {
+            siblings.push(index ^ 1);
+            index /= 2;
+        }
+        siblings
+    }
+
+    /// The current bindings and the tree, for setting up OSM.
+    fn pairs(&self) -> Vec<(Key, Value)> {
+        let mut pairs: Vec<(Key, Value)> = self.bindings.iter().enumerate()
+            .map(|(user, &value)| (user as Key, value))
+            .collect();
+        pairs.extend((1..self.nodes.len()).map(|i| (node_key(i), self.nodes[i])));
+        pairs
+    }
+
+    fn lookup(&self, user: usize) -> Vec<Op> {
+        let mut ops = vec![Op::Search { key: user as Key, offset: 0, range: 1 }];
+        ops.extend(self.siblings(user).into_iter().map(|i| Op::Search { key: node_key(i), offset: 0, range: 1 }));
+        ops
+    }
+
+    /// Checks the results of `lookup(user)`: the user's current binding,
+    /// and a path that leads from it to the current root.
+    fn verify_lookup(&self, user: usize, results: &[Vec<Value>]) -> Result<(), BenchError> {
+        if results.is_empty() || results.iter().any(|values| values.len() != 1) {
+            return Err(BenchError::IncorrectResult);
+        }
+        let value = results[0][0];
+        let path: Vec<u64> = results[1..].iter().map(|values| values[0]).collect();
+        if value != self.bindings[user] || root_from_path(user, self.n_leaves, value, &path) != self.root() {
+            return Err(BenchError::IncorrectResult);
+        }
+        Ok(())
+    }
+
+    /// Publishes a new key for `user`.
+    fn update(&mut self, user: usize, public_key: u64) -> Result<Vec<Op>, BenchError> {
+        let (version, _) = unpack(self.bindings[user]);
+        if version == MAX_VERSION {
+            return Err(BenchError::Osm(OsmStatus::CapacityExceeded));
+        }
+        let value = binding(version + 1, public_key);
+        self.bindings[user] = value;
+        let mut ops = vec![Op::Insert { key: user as Key, value }];
+        let mut index = self.n_leaves + user;
+        let mut hash = leaf_hash(user, value);
+        loop {
+            ops.push(Op::Update { key: node_key(index), old: self.nodes[index], new: hash });
+            self.nodes[index] = hash;
+            if index == 1 {
+                break;
+            }
+            hash = if index % 2 == 0 {
+                node_hash(hash, self.nodes[index + 1])
+            } else {
+                node_hash(self.nodes[index - 1], hash)
+            };
+            index /= 2;
+        }
+        Ok(ops)
+    }
+}
+
+/// Runs `ops` against an OSM holding `pairs`, with room for `extra` more,
+/// and fails if any of them fails. Returns the results of every operation,
+/// and the latency of each of the first `n_groups` groups of `group_len`
+/// operations.
+fn execute<B: EnclaveBackend>(
+    backend: &B,
+    pairs: Vec<(Key, Value)>,
+    extra: usize,
+    ops: &[Op],
+    group_len: usize,
+    n_groups: usize,
+) -> Result<(Vec<Vec<Value>>, Latencies), BenchError> {
+    let capacity = pairs.len() + extra;
+    let (osm_client, mut server) = microbenchmarks::setup_osm(backend, capacity, pairs);
+    progress!("[+] Done with setup");
+
+    let session_id = microbenchmarks::open_session(backend, &osm_client, &mut server)?;
+    let executed = microbenchmarks::execute_groups(backend, session_id, ops, &batch::chunks(n_groups, group_len));
+    backend.osm_close(session_id)?;
+    let (results, latencies) = executed?;
+    let values = results.into_iter()
+        .map(|result| result.map_err(BenchError::Osm))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((values, latencies))
+}
+
+/// Runs `MEASUREMENTS` lookups of random users in a directory of
+/// `n_users`, verifying every key against the root.
+pub fn lookup<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, n_users: usize) -> Result<Latencies, BenchError> {
+    progress!("\n[+] Users: {}, Lookups: {}", n_users, MEASUREMENTS);
+    if n_users == 0 {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+    let directory = Directory::new(rng, n_users);
+    let users: Vec<usize> = (0..MEASUREMENTS).map(|_| rng.gen_range(0, n_users)).collect();
+    let ops: Vec<Op> = users.iter().flat_map(|&user| directory.lookup(user)).collect();
+    let group_len = ops.len() / MEASUREMENTS;
+
+    let (values, latencies) = execute(backend, directory.pairs(), 0, &ops, group_len, MEASUREMENTS)?;
+    for (&user, group) in users.iter().zip(values.chunks(group_len)) {
+        directory.verify_lookup(user, group)?;
+    }
+    progress!(
+        "[+] Lookups: {}, path length: {}, avg. time (s): {}",
+        MEASUREMENTS, group_len - 1, latencies.mean()
+    );
+    Ok(latencies)
+}
+
+/// Publishes `MEASUREMENTS` new keys for random users in a directory of
+/// `n_users`, then looks every updated user up again to check that the new
+/// key sorts first and that its path leads to the new root. Only the
+/// updates are timed.
+pub fn update<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, n_users: usize) -> Result<Latencies, BenchError> {
+    progress!("\n[+] Users: {}, Updates: {}", n_users, MEASUREMENTS);
+    if n_users == 0 {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+    let mut directory = Directory::new(rng, n_users);
+    let initial = directory.pairs();
+    let mut ops = Vec::new();
+    let mut users = Vec::with_capacity(MEASUREMENTS);
+    for _ in 0..MEASUREMENTS {
+        let user = rng.gen_range(0, n_users);
+        let public_key = rng.gen();
+        ops.extend(directory.update(user, public_key)?);
+        users.push(user);
+    }
+    let group_len = ops.len() / MEASUREMENTS;
+    let n_update_ops = ops.len();
+    for &user in users.iter() {
+        ops.extend(directory.lookup(user));
+    }
+    let lookup_len = group_len - 1;
+
+    // An update fails inside the enclave, and so does the batch, if the
+    // node values it replaces are not the ones the model expects.
+    let (values, latencies) = execute(backend, initial, MEASUREMENTS, &ops, group_len, MEASUREMENTS)?;
+    for (&user, group) in users.iter().zip(values[n_update_ops..].chunks(lookup_len)) {
+        directory.verify_lookup(user, group)?;
+    }
+    progress!(
+        "[+] Updates: {}, nodes per update: {}, avg. time (s): {}",
+        MEASUREMENTS, group_len - 1, latencies.mean()
+    );
+    Ok(latencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use microbenchmarks::workload_rng;
+
+    fn path(directory: &Directory, user: usize) -> Vec<u64> {
+        directory.siblings(user).into_iter().map(|i| directory.nodes[i]).collect()
+    }
+
+    #[test]
+    fn every_path_leads_to_the_root() {
+        // Not a power of two, so some leaves are empty.
+        let directory = Directory::new(&mut workload_rng(1), 5);
+        for user in 0..5 {
+            let value = directory.bindings[user];
+            let path = path(&directory, user);
+            assert_eq!(path.len(), 3);
+            assert_eq!(root_from_path(user, directory.n_leaves, value, &path), directory.root());
+            assert!(root_from_path(user, directory.n_leaves, value ^ 1, &path) != directory.root());
+        }
+    }
+
+    #[test]
+    fn updates_move_the_root() {
+        let mut directory = Directory::new(&mut workload_rng(1), 5);
+        let (old_root, old_value) = (directory.root(), directory.bindings[3]);
+        let ops = directory.update(3, 42).unwrap();
+        // The new version, then every node from the leaf up to the root.
+        assert_eq!(ops.len(), 1 + 4);
+        assert!(directory.root() != old_root);
+        assert_eq!(unpack(directory.bindings[3]), (1, 42));
+        // Newer versions sort first.
+        assert!(directory.bindings[3] < old_value);
+
+        let current = |d: &Directory| -> Vec<Vec<Value>> {
+            let mut results = vec![vec![d.bindings[3]]];
+            results.extend(path(d, 3).into_iter().map(|hash| vec![hash]));
+            results
+        };
+        assert_eq!(directory.verify_lookup(3, &current(&directory)), Ok(()));
+        let mut stale = current(&directory);
+        stale[0][0] = old_value;
+        assert_eq!(directory.verify_lookup(3, &stale), Err(BenchError::IncorrectResult));
+        assert_eq!(directory.verify_lookup(3, &[]), Err(BenchError::IncorrectResult));
+    }
+}
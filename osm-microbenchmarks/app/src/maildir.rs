@@ -0,0 +1,143 @@
+//! Builds the inverted index that `se` loads (see `enron.rs`) from a
+//! maildir tree, such as `kaminski-v` from the Enron corpus.
+//!
+//! Every regular file under the root is a message and gets a document id,
+//! in path order. Only the body (everything after the first blank line) is
+//! indexed: it is lowercased and split into alphabetic words, and words
+//! shorter than `MIN_WORD_LEN` or longer than `MAX_WORD_LEN` and stopwords
+//! are dropped.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+const MIN_WORD_LEN: usize = 3;
+/// Longer "words" are almost always encoded attachments or URLs.
+const MAX_WORD_LEN: usize = 30;
+
+/// Sorted, for `binary_search`.
+const STOPWORDS: &'static [&'static str] = &[
+    "about", "above", "after", "again", "against", "all", "also", "and", "any", "are", "because",
+    "been", "before", "being", "below", "between", "both", "but", "can", "cannot", "could", "did",
+    "does", "doing", "down", "during", "each", "few", "for", "from", "further", "had", "has",
+    "have", "having", "her", "here", "hers", "herself", "him", "himself", "his", "how", "into",
+    "its", "itself", "just", "let", "more", "most", "much", "must", "myself", "nor", "not", "now",
+    "off", "once", "only", "other", "ought", "our", "ours", "ourselves", "out", "over", "own",
+    "same", "shall", "she", "should", "some", "such", "than", "that", "the", "their", "theirs",
+    "them", "themselves", "then", "there", "these", "they", "this", "those", "through", "too",
+    "under", "until", "very", "was", "were", "what", "when", "where", "which", "while", "who",
+    "whom", "why", "will", "with", "would", "you", "your", "yours", "yourself", "yourselves",
+];
+
+/// What went into an index.
+pub struct Stats {
+    pub documents: usize,
+    pub keywords: usize,
+    pub postings: usize,
+    pub max_posting_len: usize,
+    pub mean_posting_len: f64,
+}
+
+/// The body of a message: everything after the headers.
+fn body(message: &str) -> &str {
+    match message.find("\n\n").or_else(|| message.find("\r\n\r\n")) {
+        Some(end) => &message[end..],
+        None => "",
+    }
+}
+
+/// The distinct keywords of a message body.
+pub fn keywords(body: &str) -> BTreeSet<String> {
+    body.split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|word| word.len() >= MIN_WORD_LEN && word.len() <= MAX_WORD_LEN)
+        .map(|word| word.to_ascii_lowercase())
+        .filter(|word| STOPWORDS.binary_search(&word.as_str()).is_err())
+        .collect()
+}
+
+/// All regular files under `dir`, in path order. Hidden entries are skipped.
+fn messages(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        let hidden = path.file_name().map_or(false, |name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            messages(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Indexes every message under `root` and writes the posting lists to
+/// `output` as JSON.
+pub fn build(root: &str, output: &str) -> io::Result<Stats> {
+    let mut files = Vec::new();
+    messages(Path::new(root), &mut files)?;
+    progress!("[+] Indexing {} messages under {}", files.len(), root);
+
+    let mut postings: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for (id, path) in files.iter().enumerate() {
+        // Old mail is not always valid UTF-8; the words that matter are ASCII.
+        let message = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+        for keyword in keywords(body(&message)) {
+            // Ids increase, so every posting list stays sorted.
+            postings.entry(keyword).or_insert_with(Vec::new).push(id as u64);
+        }
+    }
+
+    let mut out = io::BufWriter::new(fs::File::create(output)?);
+    serde_json::to_writer(&mut out, &postings)?;
+    out.flush()?;
+
+    let n_postings: usize = postings.values().map(|ids| ids.len()).sum();
+    Ok(Stats {
+        documents: files.len(),
+        keywords: postings.len(),
+        postings: n_postings,
+        max_posting_len: postings.values().map(|ids| ids.len()).max().unwrap_or(0),
+        mean_posting_len: if postings.is_empty() { 0.0 } else { n_postings as f64 / postings.len() as f64 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stopwords_are_sorted() {
+        assert!(STOPWORDS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn keywords_are_lowercase_words_that_are_not_stopwords() {
+        let words = keywords("The Gas-price forecast, for Q3: gas@enron.com; about 100 units.");
+        let expected: BTreeSet<String> = ["com", "enron", "forecast", "gas", "price", "units"]
+            .iter().map(|w| w.to_string()).collect();
+        assert_eq!(words, expected);
+    }
+
+    #[test]
+    fn keywords_drop_short_and_long_words() {
+        let long = "x".repeat(MAX_WORD_LEN + 1);
+        let words = keywords(&format!("an ox {} {}", long, &long[1..]));
+        assert_eq!(words.len(), 1);
+        assert!(words.contains(&long[1..]));
+    }
+
+    #[test]
+    fn only_the_body_is_indexed() {
+        assert_eq!(body("Subject: hi\nFrom: a\n\nHello"), "\n\nHello");
+        assert_eq!(body("Subject: hi\r\n\r\nHello"), "\r\n\r\nHello");
+        assert_eq!(body("Subject: hi"), "");
+    }
+}
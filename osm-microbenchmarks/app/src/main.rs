@@ -27,7 +27,10 @@
 // OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 extern crate sgx_types;
+#[cfg(not(feature = "native"))]
 extern crate sgx_urts;
+#[cfg(feature = "native")]
+extern crate osm_microbenchmarks;
 extern crate generic_array;
 
 extern crate path_oram;
@@ -37,24 +40,77 @@ extern crate time;
 #[macro_use]
 extern crate structopt;
 extern crate pretty_env_logger;
+#[cfg(not(feature = "native"))]
 extern crate dirs;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+extern crate bincode;
+#[macro_use]
+extern crate lazy_static;
 
 use structopt::StructOpt;
+use rand::{OsRng, Rng};
 
 use sgx_types::*;
+#[cfg(not(feature = "native"))]
 use sgx_urts::SgxEnclave;
 
+use backend::{EnclaveBackend, TracingBackend};
+use distribution::Distribution;
+use report::{Metadata, OutputFormat, Params, Reporter};
+
+#[cfg(not(feature = "native"))]
 use std::io::{Read, Write};
+#[cfg(not(feature = "native"))]
 use std::fs;
+#[cfg(not(feature = "native"))]
 use std::path;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set when the results go out as JSON or CSV, to keep progress messages
+/// off stdout.
+static PROGRESS_TO_STDERR: AtomicBool = AtomicBool::new(false);
 
+/// Prints a progress or log message: to stdout alongside text results, to
+/// stderr when stdout carries JSON or CSV.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if ::PROGRESS_TO_STDERR.load(::std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod backend;
+mod batch;
+mod distribution;
+mod enron;
+mod errors;
+mod experiment;
+mod key_transparency;
+mod maildir;
+mod marshal;
 mod microbenchmarks;
+mod replay;
+mod report;
+mod setup;
+mod signal;
+mod stats;
+mod timing;
+mod trace;
+mod ycsb;
 
 #[derive(StructOpt, Debug)]
 /// Run Signal benchmarks
 #[structopt(name = "signal")]
 struct Signal {
-    #[structopt(help = "Initial size of the storage", default_value = "1024")]
+    #[structopt(help = "Largest directory size, halved down to 10000", default_value = "160000")]
     initial_size: usize
 }
 
@@ -62,7 +118,7 @@ struct Signal {
 /// Run Key Transparency benchmarks
 #[structopt(name = "kt")]
 struct KeyTransparency {
-    #[structopt(help = "Initial size of the storage", default_value = "1024")]
+    #[structopt(help = "Largest directory size, halved down to 10000", default_value = "160000")]
     initial_size: usize
 }
 
@@ -76,6 +132,8 @@ struct SearchableEncryption {
     number_of_results: usize,
     #[structopt(help = "Number of documents to insert", default_value = "10")]
     number_of_documents: usize,
+    #[structopt(long = "max-terms", help = "Largest number of keywords in AND/OR queries", default_value = "5")]
+    max_terms: usize,
 }
 
 #[derive(StructOpt, Debug)]
@@ -103,6 +161,62 @@ enum OsmMicrobenchmarks {
         initial_size: usize
     },
 
+    #[structopt(name = "mixed")]
+    Mixed {
+        #[structopt(help = "Number of operations in the batch", default_value = "1000")]
+        number_of_operations: usize,
+        #[structopt(help = "Initial size of the storage", default_value = "1024")]
+        initial_size: usize,
+    },
+
+    #[structopt(name = "throughput")]
+    Throughput {
+        #[structopt(help = "Number of client threads", default_value = "4")]
+        number_of_threads: usize,
+        #[structopt(help = "Number of searches per thread", default_value = "1000")]
+        number_of_operations: usize,
+        #[structopt(help = "Initial size of each client's storage", default_value = "1024")]
+        initial_size: usize,
+    },
+
+    #[structopt(name = "save")]
+    Save {
+        #[structopt(help = "Path prefix of the saved state")]
+        path: String,
+        #[structopt(help = "Initial size of the storage", default_value = "1024")]
+        initial_size: usize,
+    },
+
+    #[structopt(name = "resume")]
+    Resume {
+        #[structopt(help = "Path prefix of the saved state")]
+        path: String,
+        #[structopt(help = "Number of searches to run after restoring", default_value = "1000")]
+        number_of_searches: usize,
+    },
+
+    #[structopt(name = "ycsb")]
+    Ycsb {
+        #[structopt(help = "YCSB core workload: a, b, c, d, e or f")]
+        workload: ycsb::Workload,
+        #[structopt(help = "Number of records loaded before the run", default_value = "1000")]
+        record_count: usize,
+        #[structopt(help = "Number of operations in the run", default_value = "1000")]
+        operation_count: usize,
+        #[structopt(help = "Number of values (fields) per record", default_value = "10")]
+        values_per_key: usize,
+    },
+
+    #[structopt(name = "replay")]
+    Replay {
+        #[structopt(help = "Path of the trace")]
+        trace: String,
+        #[structopt(help = "Number of random pairs to load besides those the trace needs", default_value = "0")]
+        initial_size: usize,
+        #[structopt(long = "realtime", help = "Issue each operation in its own ECALL at its timestamp")]
+        realtime: bool,
+    },
+
     #[structopt(name = "delete-one")]
     DeleteOne { 
         #[structopt(help = "Number of keys to delete", default_value = "1")]
@@ -135,6 +249,58 @@ enum OramMicrobenchmarks {
         #[structopt(help = "Block size", default_value = "160")]
         block_size: usize,
     },
+    #[structopt(name = "replay")]
+    Replay {
+        #[structopt(help = "Path of the trace")]
+        trace: String,
+        #[structopt(help = "Initial size of the storage (grown to fit the trace)", default_value = "0")]
+        initial_size: usize,
+        #[structopt(help = "Block size", default_value = "160")]
+        block_size: usize,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+/// Build the index that `se` loads from a maildir tree
+#[structopt(name = "build-index")]
+struct BuildIndex {
+    #[structopt(help = "Root of the maildir tree, e.g. kaminski-v")]
+    maildir: String,
+    #[structopt(help = "Where to write the index", default_value = "enron-index.json")]
+    output: String,
+}
+
+#[derive(StructOpt, Debug)]
+struct SetupCommand {
+    #[structopt(subcommand)]
+    setup: SetupBenchmarks
+}
+
+#[derive(StructOpt, Debug)]
+/// Time the setup stages that precede the other benchmarks
+#[structopt(name = "setup")]
+enum SetupBenchmarks {
+    #[structopt(name = "osm")]
+    Osm {
+        #[structopt(help = "Number of keys", default_value = "1024")]
+        initial_size: usize,
+        #[structopt(help = "Number of values per key", default_value = "1")]
+        values_per_key: usize,
+    },
+    #[structopt(name = "oram")]
+    Oram {
+        #[structopt(help = "Number of blocks", default_value = "1024")]
+        initial_size: usize,
+        #[structopt(help = "Block size", default_value = "160")]
+        block_size: usize,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "run")]
+struct RunCommand {
+    #[structopt(help = "Experiment file (TOML, or JSON if it ends in .json)")]
+    experiment: String,
 }
 
 #[derive(StructOpt, Debug)]
@@ -149,18 +315,81 @@ enum OptionsCommand {
     Signal(Signal),
     #[structopt(name = "kt")]
     KT(KeyTransparency),
+    #[structopt(name = "build-index")]
+    BuildIndex(BuildIndex),
+    #[structopt(name = "setup")]
+    Setup(SetupCommand),
+    #[structopt(name = "run")]
+    Run(RunCommand),
 }
 
 #[derive(StructOpt, Debug)]
 struct Options {
+    #[structopt(long = "trials", help = "Number of times to run each parameter point", default_value = "1")]
+    trials: usize,
+    #[structopt(long = "seed", help = "Seed of the generated datasets and queries (random if omitted); \
+                                          every point derives its own seed from it")]
+    seed: Option<u64>,
+    #[structopt(long = "point-seed", help = "Use this seed for every point instead of deriving one, \
+                                                to rerun a point alone from the seed it reported")]
+    point_seed: Option<u64>,
+    #[structopt(long = "distribution", help = "Distribution of the queried keys: repeat, uniform, zipf:<skew>, \
+                                              hotspot:<keys>:<ops>, sequential or fresh \
+                                              (default: repeat for osm range, uniform for oram and se)")]
+    distribution: Option<Distribution>,
+    #[structopt(long = "record", help = "Record the operations the benchmarks execute to this trace file")]
+    record: Option<String>,
+    #[structopt(long = "output", help = "Format of the results: text, json or csv; with json and csv, \
+                                            progress messages go to stderr", default_value = "text")]
+    output: OutputFormat,
+    #[structopt(long = "output-file", help = "Write the results to this file instead of stdout")]
+    output_file: Option<String>,
     #[structopt(subcommand)]
     options: OptionsCommand
 }
 
 
+/// The enclave (or its stand-in) that the backend issues ECALLs to.
+#[cfg(not(feature = "native"))]
+type Enclave = SgxEnclave;
+#[cfg(feature = "native")]
+type Enclave = ();
+
+#[cfg(not(feature = "native"))]
+fn open_backend(enclave: &Enclave) -> backend::SgxBackend {
+    backend::SgxBackend::new(enclave)
+}
+#[cfg(feature = "native")]
+fn open_backend(_enclave: &Enclave) -> backend::NativeBackend {
+    backend::NativeBackend
+}
+
+#[cfg(not(feature = "native"))]
+fn destroy_enclave(enclave: Enclave) {
+    enclave.destroy();
+}
+#[cfg(feature = "native")]
+fn destroy_enclave(_enclave: Enclave) {}
+
+/// Tears down the enclave and exits with a failure status, for errors that
+/// stop the run before or between benchmarks.
+fn fail(enclave: Enclave) -> ! {
+    destroy_enclave(enclave);
+    process::exit(1)
+}
+
+#[cfg(not(feature = "native"))]
 static ENCLAVE_FILE: &'static str = "enclave.signed.so";
+#[cfg(not(feature = "native"))]
 static ENCLAVE_TOKEN: &'static str = "enclave.token";
 
+#[cfg(feature = "native")]
+fn init_enclave() -> SgxResult<Enclave> {
+    progress!("[+] Running the trusted code natively, without SGX");
+    Ok(())
+}
+
+#[cfg(not(feature = "native"))]
 fn init_enclave() -> SgxResult<SgxEnclave> {
     
     let mut launch_token: sgx_launch_token_t = [0; 1024];
@@ -172,12 +401,12 @@ fn init_enclave() -> SgxResult<SgxEnclave> {
     let mut home_dir = path::PathBuf::new();
     let use_token = match dirs::home_dir() {
         Some(path) => {
-            println!("[+] Home dir is {}", path.display());
+            progress!("[+] Home dir is {}", path.display());
             home_dir = path;
             true
         },
         None => {
-            println!("[-] Cannot get home dir");
+            progress!("[-] Cannot get home dir");
             false
         }
     };
@@ -186,15 +415,15 @@ fn init_enclave() -> SgxResult<SgxEnclave> {
     if use_token == true {
         match fs::File::open(&token_file) {
             Err(_) => {
-                println!("[-] Open token file {} error! Will create one.", token_file.as_path().to_str().unwrap());
+                progress!("[-] Open token file {} error! Will create one.", token_file.as_path().to_str().unwrap());
             },
             Ok(mut f) => {
-                println!("[+] Open token file success! ");
+                progress!("[+] Open token file success! ");
                 match f.read(&mut launch_token) {
                     Ok(1024) => {
-                        println!("[+] Token file valid!");
+                        progress!("[+] Token file valid!");
                     },
-                    _ => println!("[+] Token file invalid, will create new token file"),
+                    _ => progress!("[+] Token file invalid, will create new token file"),
                 }
             }
         }
@@ -216,12 +445,12 @@ fn init_enclave() -> SgxResult<SgxEnclave> {
         match fs::File::create(&token_file) {
             Ok(mut f) => {
                 match f.write_all(&launch_token) {
-                    Ok(()) => println!("[+] Saved updated launch token!"),
-                    Err(_) => println!("[-] Failed to save updated launch token!"),
+                    Ok(()) => progress!("[+] Saved updated launch token!"),
+                    Err(_) => progress!("[-] Failed to save updated launch token!"),
                 }
             },
             Err(_) => {
-                println!("[-] Failed to save updated enclave token, but doesn't matter");
+                progress!("[-] Failed to save updated enclave token, but doesn't matter");
             },
         }
     }
@@ -229,128 +458,386 @@ fn init_enclave() -> SgxResult<SgxEnclave> {
     Ok(enclave)
 }
 
+fn build_index(inner: &BuildIndex) {
+    match maildir::build(&inner.maildir, &inner.output) {
+        Ok(stats) => {
+            progress!("[+] Wrote {}", inner.output);
+            progress!(
+                "[+] Documents: {}, Keywords: {}, Postings: {}, Max. posting length: {}, Mean posting length: {:.2}",
+                stats.documents, stats.keywords, stats.postings, stats.max_posting_len, stats.mean_posting_len
+            );
+        },
+        Err(e) => {
+            progress!("[-] Cannot build the index from {}: {}", inner.maildir, e);
+            process::exit(1);
+        },
+    }
+}
+
 fn main() { 
     let options = Options::from_args();
+    PROGRESS_TO_STDERR.store(options.output != OutputFormat::Text, Ordering::Relaxed);
+    // Building the index does not need the enclave.
+    if let OptionsCommand::BuildIndex(ref inner) = options.options {
+        build_index(inner);
+        return;
+    }
     let enclave = match init_enclave() {
         Ok(r) => {
-            println!("[+] Init Enclave Successful {}!", r.geteid());
+            progress!("[+] Init Enclave Successful!");
             r
         },
         Err(x) => {
-            println!("[-] Init Enclave Failed {}!", x.as_str());
-            return;
+            progress!("[-] Init Enclave Failed {}!", x.as_str());
+            process::exit(1);
         },
     };
-    let result = match options.options {
+    let backend = match options.record {
+        Some(ref path) => match TracingBackend::recording(open_backend(&enclave), path) {
+            Ok(backend) => backend,
+            Err(e) => {
+                progress!("[-] Cannot create the trace file: {}", e);
+                fail(enclave);
+            },
+        },
+        None => TracingBackend::new(open_backend(&enclave)),
+    };
+    let trials = options.trials.max(1);
+    let output_file = options.output_file.as_ref().map(|s| s.as_str());
+    let seed = options.seed.unwrap_or_else(|| OsRng::new().unwrap().gen());
+    progress!("[+] Workload seed: {}", seed);
+    let metadata = Metadata::collect(backend.name(), seed);
+    let mut reporter = match Reporter::new(options.output, output_file, metadata, options.point_seed) {
+        Ok(reporter) => reporter,
+        Err(e) => {
+            progress!("[-] Cannot open the output file: {}", e);
+            fail(enclave);
+        },
+    };
+    // The defaults are what these benchmarks measured before the
+    // distribution could be chosen.
+    let search_distribution = options.distribution.unwrap_or(Distribution::Repeat);
+    let oram_distribution = options.distribution.unwrap_or(Distribution::Uniform);
+    let mut failures = 0;
+    match options.options {
         OptionsCommand::Osm(inner) => {
             match inner.osm {
                 OsmMicrobenchmarks::Range => {
-                    println!("Running osm::range: \n
+                    progress!("Running osm::range: \n
                              Retrieving 10 results for 2^24 key-value pairs, with 1 - 2^8 values per key \n");
-                    let mut actual_result = 0;
                     for i in 16..24 {
-                        actual_result += microbenchmarks::search(&enclave, 1 << i, 1 << (24 - i), 10).from_key();
+                        let params = Params {
+                            n_keys: Some(1 << i),
+                            vals_per_key: Some(1 << (24 - i)),
+                            range: Some(10),
+                            distribution: Some(search_distribution),
+                            ..Params::default()
+                        };
+                        failures += reporter.measure("osm::range", params, trials, |rng| {
+                            microbenchmarks::search(&backend, rng, search_distribution, 1 << i, 1 << (24 - i), 10)
+                        });
                     }
-                    println!("\n----------------------------\n");
-                    println!("Retrieving 1, 10, 20, ..., 60 results for 2^24 key-value pairs, with 2^10 values per key \n");
+                    progress!("\n----------------------------\n");
+                    progress!("Retrieving 1, 10, 20, ..., 60 results for 2^24 key-value pairs, with 2^10 values per key \n");
                     for i in vec![1, 10, 20, 30, 40, 50, 60] {
-                        actual_result += microbenchmarks::search(&enclave, 1 << (24 - 10), 1 << 10, i).from_key();
+                        let params = Params {
+                            n_keys: Some(1 << (24 - 10)),
+                            vals_per_key: Some(1 << 10),
+                            range: Some(i),
+                            distribution: Some(search_distribution),
+                            ..Params::default()
+                        };
+                        failures += reporter.measure("osm::range", params, trials, |rng| {
+                            microbenchmarks::search(&backend, rng, search_distribution, 1 << (24 - 10), 1 << 10, i)
+                        });
                     }
-                    sgx_status_t::from_repr(actual_result).unwrap()
-
                 }
                 OsmMicrobenchmarks::InsertMany => {
-                    println!("Running osm::insert_many:\n
+                    progress!("Running osm::insert_many:\n
                              Inserting 100 items into storage of size 2^16 - 2^25");
-                    let mut actual_result = 0;
                     for i in 16..25 {
-                        // If the operation is successful, it returns 0.
-                        actual_result += microbenchmarks::insert_many(&enclave, 1 << i, 100).from_key();
+                        let params = Params { n_keys: Some(1 << i), n_ops: Some(100), ..Params::default() };
+                        failures += reporter.measure("osm::insert_many", params, trials, |rng| {
+                            microbenchmarks::insert_many(&backend, rng, 1 << i, 100)
+                        });
                     }
-                    println!("\n----------------------------\n");
-                    sgx_status_t::from_repr(actual_result).unwrap()
+                    progress!("\n----------------------------\n");
                 }
                 OsmMicrobenchmarks::InsertOne { number_of_keys_to_insert, initial_size} => {
-                    println!("Running osm::insert_one");
-                    let result = microbenchmarks::insert_one(&enclave, initial_size, number_of_keys_to_insert);
-                    println!("\n----------------------------\n");
-                    result
-
+                    progress!("Running osm::insert_one");
+                    let params = Params {
+                        n_keys: Some(initial_size),
+                        n_ops: Some(number_of_keys_to_insert),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("osm::insert_one", params, trials, |rng| {
+                        microbenchmarks::insert_one(&backend, rng, initial_size, number_of_keys_to_insert)
+                    });
+                    progress!("\n----------------------------\n");
+                }
+                OsmMicrobenchmarks::Mixed { number_of_operations, initial_size } => {
+                    progress!("Running osm::mixed");
+                    let params = Params {
+                        n_keys: Some(initial_size),
+                        n_ops: Some(number_of_operations),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("osm::mixed", params, trials, |rng| {
+                        microbenchmarks::mixed(&backend, rng, initial_size, number_of_operations)
+                    });
+                    progress!("\n----------------------------\n");
+                }
+                OsmMicrobenchmarks::Throughput { number_of_threads, number_of_operations, initial_size } => {
+                    progress!("Running osm::throughput");
+                    let params = Params {
+                        n_keys: Some(initial_size),
+                        n_ops: Some(number_of_operations),
+                        n_threads: Some(number_of_threads),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("osm::throughput", params, trials, |rng| {
+                        microbenchmarks::throughput(&backend, rng, number_of_threads, initial_size, number_of_operations)
+                    });
+                    progress!("\n----------------------------\n");
+                }
+                OsmMicrobenchmarks::Save { path, initial_size } => {
+                    progress!("Running osm::save");
+                    let params = Params { n_keys: Some(initial_size), ..Params::default() };
+                    failures += reporter.measure("osm::save", params, trials, |rng| {
+                        microbenchmarks::save(&backend, rng, initial_size, &path)
+                    });
+                    progress!("\n----------------------------\n");
+                }
+                OsmMicrobenchmarks::Resume { path, number_of_searches } => {
+                    progress!("Running osm::resume");
+                    let params = Params { n_ops: Some(number_of_searches), ..Params::default() };
+                    failures += reporter.measure("osm::resume", params, trials, |rng| {
+                        microbenchmarks::resume(&backend, rng, &path, number_of_searches)
+                    });
+                    progress!("\n----------------------------\n");
+                }
+                OsmMicrobenchmarks::Ycsb { workload, record_count, operation_count, values_per_key } => {
+                    progress!("Running osm::ycsb");
+                    let params = Params {
+                        n_keys: Some(record_count),
+                        vals_per_key: Some(values_per_key),
+                        n_ops: Some(operation_count),
+                        ..Params::default()
+                    };
+                    let benchmark = format!("osm::ycsb_{}", workload);
+                    failures += reporter.measure(&benchmark, params, trials, |rng| {
+                        ycsb::run(&backend, rng, workload, record_count, operation_count, values_per_key)
+                    });
+                    progress!("\n----------------------------\n");
+                }
+                OsmMicrobenchmarks::Replay { trace, initial_size, realtime } => {
+                    progress!("Running osm::replay");
+                    let params = Params { n_keys: Some(initial_size), ..Params::default() };
+                    failures += reporter.measure("osm::replay", params, trials, |rng| {
+                        let entries = trace::read(&trace)?;
+                        replay::osm(&backend, rng, &entries, initial_size, realtime)
+                    });
+                    progress!("\n----------------------------\n");
                 }
                 OsmMicrobenchmarks::DeleteOne { number_of_keys_to_delete, initial_size} => {
-                    println!("Running osm::insert_one");
-                    let result = microbenchmarks::delete_one(&enclave, initial_size, number_of_keys_to_delete);
-                    println!("\n----------------------------\n");
-                    result
+                    progress!("Running osm::delete_one");
+                    let params = Params {
+                        n_keys: Some(initial_size),
+                        n_ops: Some(number_of_keys_to_delete),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("osm::delete_one", params, trials, |rng| {
+                        microbenchmarks::delete_one(&backend, rng, initial_size, number_of_keys_to_delete)
+                    });
+                    progress!("\n----------------------------\n");
                 }
             }
         }
         OptionsCommand::Oram(inner) => {
             match inner.oram {
                 OramMicrobenchmarks::ZeroTrace { initial_size } => {
-                    println!("Running ZeroTrace");
-                    let result = microbenchmarks::zerotrace(&enclave, initial_size);
-                    println!("\n----------------------------\n");
-                    result
+                    progress!("Running ZeroTrace");
+                    let params = Params {
+                        n_keys: Some(initial_size),
+                        block_size: Some(8),
+                        distribution: Some(oram_distribution),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("oram::zerotrace", params, trials, |rng| {
+                        microbenchmarks::zerotrace(&backend, rng, oram_distribution, initial_size)
+                    });
+                    progress!("\n----------------------------\n");
                 }
                 OramMicrobenchmarks::OramAccess { block_size, initial_size } => {
-                    println!("Running DORAM Access");
-                    println!("\nItems: {}, Blocksize: {}", initial_size, block_size);
-                    let result = microbenchmarks::doram(&enclave, initial_size, block_size as _);
-                    println!("\n----------------------------\n");
-                    result
+                    progress!("Running DORAM Access");
+                    progress!("\nItems: {}, Blocksize: {}", initial_size, block_size);
+                    let params = Params {
+                        n_keys: Some(initial_size),
+                        block_size: Some(block_size),
+                        distribution: Some(oram_distribution),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("oram::access", params, trials, |rng| {
+                        microbenchmarks::doram(&backend, rng, oram_distribution, initial_size, block_size as _)
+                    });
+                    progress!("\n----------------------------\n");
+                }
+                OramMicrobenchmarks::Replay { trace, initial_size, block_size } => {
+                    progress!("Running ORAM replay");
+                    let params = Params {
+                        n_keys: Some(initial_size),
+                        block_size: Some(block_size),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("oram::replay", params, trials, |_| {
+                        let entries = trace::read(&trace)?;
+                        replay::oram(&backend, &entries, initial_size, block_size as _)
+                    });
+                    progress!("\n----------------------------\n");
                 }
             }
         }
         OptionsCommand::SE(inner) => {
-            // TODO
-            // println!("Running SE benchmarks on the Enron dataset (specifically `kaminski-v`): average of 100 measurements");
-            // let index_location = inner.index;
-            // let number_of_results = inner.number_of_results;
-            // let number_of_documents = inner.number_of_documents;
-            // let result1 = enron::search(&enclave, &file, number_of_results).from_key();
-            // let result2 = enron::insert(&enclave, &file, number_of_documents).from_key();
-            // sgx_status_t::from_repr(result1 + result2).unwrap()
-            unimplemented!()
+            progress!("Running SE benchmarks on the Enron dataset (specifically `kaminski-v`): average of {} measurements",
+                     enron::MEASUREMENTS);
+            match enron::Index::load(&inner.index) {
+                Ok(index) => {
+                    let keyword_distribution = options.distribution.unwrap_or(Distribution::Uniform);
+                    let params = Params {
+                        n_keys: Some(index.postings.len()),
+                        range: Some(inner.number_of_results),
+                        distribution: Some(keyword_distribution),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("se::search", params, trials, |rng| {
+                        enron::search(&backend, rng, keyword_distribution, &index, inner.number_of_results)
+                    });
+                    let params = Params {
+                        n_keys: Some(index.postings.len()),
+                        n_ops: Some(inner.number_of_documents),
+                        ..Params::default()
+                    };
+                    failures += reporter.measure("se::insert", params, trials, |rng| {
+                        enron::insert(&backend, rng, &index, inner.number_of_documents)
+                    });
+                    for &connective in [enron::Connective::And, enron::Connective::Or].iter() {
+                        for n_terms in 1..inner.max_terms + 1 {
+                            let params = Params {
+                                n_keys: Some(index.postings.len()),
+                                n_terms: Some(n_terms),
+                                ..Params::default()
+                            };
+                            failures += reporter.measure(&format!("se::{}", connective), params, trials, |rng| {
+                                enron::multi_search(&backend, rng, &index, connective, n_terms)
+                            });
+                        }
+                    }
+                }
+                Err(e) => {
+                    progress!("[-] Cannot load the index {}: {}", inner.index, e);
+                    failures += 1;
+                }
+            }
         }
         OptionsCommand::Signal(inner) => {
-            // TODO
-            // println!("Running Signal benchmarks");
-            // let mut i = inner.initial_size;
-            // let mut actual_result = 0;
-            // while i >= 10000 {
-            //     let inner_result = signal::run(&enclave, i);
-            //     actual_result += result.from_key();
-            //     i /= 2;
-            // }
-            // sgx_status_t::from_repr(actual_result).unwrap()
-            unimplemented!()
+            progress!("Running Signal benchmarks: contact discovery, average of {} batches", signal::MEASUREMENTS);
+            let mut i = inner.initial_size;
+            while i >= signal::MIN_DIRECTORY_SIZE {
+                for &batch_size in signal::BATCH_SIZES.iter() {
+                    let params = Params { n_keys: Some(i), n_ops: Some(batch_size), ..Params::default() };
+                    failures += reporter.measure("signal::discovery", params, trials, |rng| {
+                        signal::run(&backend, rng, i, batch_size)
+                    });
+                }
+                i /= 2;
+            }
         }
         OptionsCommand::KT(inner) => {
-            // TODO
-            // println!("Running Key Transparency benchmarks");
-            // let mut i = inner.initial_size;
-            // let mut actual_result = 0;
-            // while i >= 10000 {
-            //     let inner_result = key_transparency::run(&enclave, i);
-            //     actual_result += result.from_key();
-            //     i /= 2;
-            // }
-            // sgx_status_t::from_repr(actual_result).unwrap()
-            unimplemented!()
+            progress!("Running Key Transparency benchmarks: average of {} lookups and updates", key_transparency::MEASUREMENTS);
+            let mut i = inner.initial_size;
+            while i >= key_transparency::MIN_DIRECTORY_SIZE {
+                let params = Params { n_keys: Some(i), ..Params::default() };
+                failures += reporter.measure("kt::lookup", params, trials, |rng| {
+                    key_transparency::lookup(&backend, rng, i)
+                });
+                failures += reporter.measure("kt::update", params, trials, |rng| {
+                    key_transparency::update(&backend, rng, i)
+                });
+                i /= 2;
+            }
         }
-    };
-
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {},
-        _ => {
-            println!("[-] ECALL Enclave Failed {}!", result.as_str());
-            return;
+        OptionsCommand::BuildIndex(_) => unreachable!(),
+        OptionsCommand::Setup(inner) => {
+            match inner.setup {
+                SetupBenchmarks::Osm { initial_size, values_per_key } => {
+                    progress!("Running setup::osm: timing the setup of {} keys with {} values each", initial_size, values_per_key);
+                    let params = Params {
+                        n_keys: Some(initial_size),
+                        vals_per_key: Some(values_per_key),
+                        ..Params::default()
+                    };
+                    failures += setup::report(&mut reporter, "setup::osm", params, trials, |rng| {
+                        setup::osm(&backend, rng, initial_size, values_per_key)
+                    });
+                }
+                SetupBenchmarks::Oram { initial_size, block_size } => {
+                    progress!("Running setup::oram: timing the setup of {} blocks of {} bytes", initial_size, block_size);
+                    let params = Params { n_keys: Some(initial_size), block_size: Some(block_size), ..Params::default() };
+                    failures += setup::report(&mut reporter, "setup::oram", params, trials, |_| {
+                        setup::oram(initial_size, block_size)
+                    });
+                }
+            }
+        }
+        OptionsCommand::Run(inner) => {
+            let exp = match experiment::Experiment::load(&inner.experiment) {
+                Ok(exp) => exp,
+                Err(e) => {
+                    progress!("[-] {}", e);
+                    fail(enclave);
+                },
+            };
+            let (format, points) = match exp.format().and_then(|format| Ok((format, exp.points()?))) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    progress!("[-] {}: {}", inner.experiment, e);
+                    fail(enclave);
+                },
+            };
+            // The experiment's own output settings win over the global ones.
+            if format.is_some() || exp.output.is_some() {
+                let format = format.unwrap_or(options.output);
+                PROGRESS_TO_STDERR.store(format != OutputFormat::Text, Ordering::Relaxed);
+                let output_file = exp.output.as_ref().map(|s| s.as_str()).or(output_file);
+                let metadata = Metadata::collect(backend.name(), seed);
+                reporter = match Reporter::new(format, output_file, metadata, options.point_seed) {
+                    Ok(reporter) => reporter,
+                    Err(e) => {
+                        progress!("[-] Cannot open the output file: {}", e);
+                        fail(enclave);
+                    },
+                };
+            }
+            progress!(
+                "Running {}: {} parameter points, {} repetitions each",
+                exp.benchmark, points.len(), exp.repetitions
+            );
+            for point in points {
+                let params = experiment::with_defaults(&exp.benchmark, point);
+                progress!("\n[+] {}", params);
+                failures += reporter.measure(&exp.benchmark, params, exp.repetitions, |rng| {
+                    experiment::run_point(&backend, rng, &exp.benchmark, &params)
+                });
+            }
         }
     }
 
-    println!("[+] osm_search success...");
-    
-    enclave.destroy();
+    if failures == 0 {
+        progress!("[+] All benchmarks succeeded ({})", backend.name());
+    } else {
+        progress!("[-] {} benchmark(s) failed ({})", failures, backend.name());
+        fail(enclave);
+    }
+
+    destroy_enclave(enclave);
 }
@@ -0,0 +1,33 @@
+//! Encoding of the client state that is copied into the enclave.
+//!
+//! See `enclave/src/marshal.rs` for the layout; the two sides must agree on
+//! `MAGIC`, `VERSION` and the `StateKind` discriminants.
+
+use bincode;
+use serde::Serialize;
+
+pub const MAGIC: &'static [u8; 4] = b"OBLX";
+pub const VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateKind {
+    Osm = 0,
+    DOram = 1,
+}
+
+/// Encodes a client together with a header describing what it is.
+pub fn encode_state<T: Serialize>(client: &T, kind: StateKind, block_size: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(10);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.push(kind as u8);
+    let block_size = block_size as u32;
+    bytes.extend_from_slice(&[block_size as u8, (block_size >> 8) as u8, (block_size >> 16) as u8, (block_size >> 24) as u8]);
+    bytes.extend(encode(client));
+    bytes
+}
+
+/// Encodes a header-less payload, such as a list of keys.
+pub fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+    bincode::serialize(value).unwrap()
+}
@@ -1,377 +1,631 @@
-use generic_array::typenum::{U8, U16, U32, U64, U128, U160, U256, U512};
+use generic_array::typenum::{U8, U16, U32, U64, U128, U160, U256, U512, U1024, U2048, U4096};
 use generic_array::ArrayLength;
 
-use rand;
 use time;
 use osm::{OsmClient, STDOsmClient};
-use path_oram::{LocalServer, PathDOramClient, doubly_oblivious::position_map::PositionMap, OramKey, OramPos, NoPos};
-use path_oram::{TreeOramClient, BlockContent, EncN, EncBlkSize};
+use path_oram::{LocalServer, PathDOramClient, doubly_oblivious::position_map::PositionMap, OramKey, NoPos};
+use path_oram::{TreeOramClient, EncN, EncBlkSize};
 use path_oram::oram_crypto::{Encryptor, MerkleTree};
-use rand::{OsRng, Rng};
-
-use sgx_types::*;
-use sgx_urts::SgxEnclave;
+use rand::{Rng, SeedableRng, StdRng};
+use bincode;
+
+use backend::EnclaveBackend;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::sync::{Arc, Barrier};
+use std::thread;
 use pretty_env_logger;
 
-type Key = u64;
-type Value = u64;
-
-
-
-extern {
-    fn osm_search(
-        eid: sgx_enclave_id_t,
-        retval: *mut sgx_status_t,
-        osm_client_ref: usize,
-        server_ref: usize,
-        key_ref: usize,
-        range: usize
-    ) -> sgx_status_t;
-
-    fn osm_insert_many(
-        eid: sgx_enclave_id_t,
-        retval: *mut sgx_status_t,
-        osm_client_ref: usize,
-        server_ref: usize,
-        key_ref: usize,
-        key_len: usize,
-        vals_ref: usize,
-        vals_len: usize
-    ) -> sgx_status_t;
-
-    fn osm_insert_one(
-        eid: sgx_enclave_id_t,
-        retval: *mut sgx_status_t,
-        osm_client_ref: usize,
-        server_ref: usize,
-        key_ref: usize,
-        value_ref: usize
-    ) -> sgx_status_t;
-
-    fn osm_delete_one(
-        eid: sgx_enclave_id_t,
-        retval: *mut sgx_status_t,
-        osm_client_ref: usize,
-        server_ref: usize,
-        key_ref: usize,
-        value_ref: usize
-    ) -> sgx_status_t;
-
-    fn oram_zerotrace(
-        eid: sgx_enclave_id_t,
-        retval: *mut sgx_status_t,
-        oram_client_ref: usize,
-        server_ref: usize,
-        key_and_pos_ref: usize,
-        key_and_pos_len: usize
-    ) -> sgx_status_t;
-
-    fn oram_access(
-        eid: sgx_enclave_id_t,
-        retval: *mut sgx_status_t,
-        oram_client_ref: usize,
-        server_ref: usize,
-        key_and_pos_ref: usize,
-        key_and_pos_len: usize,
-        block_size: usize,
-    ) -> sgx_status_t;
+use batch::{self, BatchOutput, Op, OpResult};
+use distribution::Distribution;
+use errors::{BenchError, OsmStatus};
+use marshal::{self, StateKind};
+use setup;
+use timing::{self, Latencies};
+
+pub type Key = u64;
+pub type Value = u64;
+
+/// Generator of every dataset and query stream. Only the workload is
+/// seeded: the ORAM and OSM clients still draw their positions and keys
+/// from the OS.
+pub type WorkloadRng = StdRng;
+
+pub fn workload_rng(seed: u64) -> WorkloadRng {
+    StdRng::from_seed(&[seed as usize][..])
+}
+
+/// The seed of the `index`-th point of a run with seed `seed`. Every point
+/// gets a generator of its own, so that it can be rerun alone from its
+/// seed (with `--point-seed`).
+pub fn point_seed(seed: u64, index: u64) -> u64 {
+    let bytes: Vec<u8> = [seed, index].iter().flat_map(|&w| (0..8).map(move |i| (w >> (8 * i)) as u8)).collect();
+    hash_key(&bytes)
+}
+
+/// 64-bit FNV-1a, for storing strings and other identifiers under a `Key`
+/// that does not change between runs or builds.
+pub fn hash_key(bytes: &[u8]) -> Key {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+
+/// Copies `osm_client` into the enclave and returns the id of the session
+/// that now owns it. `server` must outlive the session.
+pub fn open_session<B: EnclaveBackend>(
+    backend: &B,
+    osm_client: &STDOsmClient<Key, Value, PathDOramClient<U160>>,
+    server: &mut LocalServer<PathDOramClient<U160>>,
+) -> Result<u64, BenchError> {
+    let client = marshal::encode_state(osm_client, StateKind::Osm, 160);
+    let server_ref = server as *mut LocalServer<PathDOramClient<U160>> as usize;
+    backend.osm_open(&client, server_ref)
+}
+
+/// Sets up an OSM of `capacity` pairs holding `pairs`. The backend sees
+/// the pairs first, so that a recorded trace can rebuild the store.
+pub fn setup_osm<B: EnclaveBackend>(
+    backend: &B,
+    capacity: usize,
+    pairs: Vec<(Key, Value)>,
+) -> (STDOsmClient<Key, Value, PathDOramClient<U160>>, LocalServer<PathDOramClient<U160>>) {
+    backend.osm_preload(&pairs);
+    STDOsmClient::<Key, Value, PathDOramClient<U160>>::setup(capacity, pairs).unwrap()
+}
+
+/// Executes `ops` in order inside the session, in a single ECALL.
+pub fn execute_batch<B: EnclaveBackend>(backend: &B, session_id: u64, ops: &[Op]) -> Result<BatchOutput, BenchError> {
+    let encoded = batch::encode(ops);
+    let mut results = vec![0; batch::result_capacity(ops)?];
+    let mut cycles = vec![0; ops.len()];
+    let start = time::precise_time_s();
+    let results_len = backend.osm_execute_batch(session_id, &encoded, &mut results, &mut cycles)?;
+    let host_secs = time::precise_time_s() - start;
+    let results = batch::decode_results(&results[..results_len], ops.len())?;
+    Ok(BatchOutput { results, cycles, host_secs })
 }
 
-pub fn insert_many(enclave: &SgxEnclave, init_size: usize, n_keys: usize) -> sgx_status_t {
-    println!(
+/// Executes `ops` in order and returns the result of every operation and
+/// the latency of every group. `groups` are ranges of `ops` in ascending
+/// order, such as the operations that make up one query.
+///
+/// The operations up to the end of the first group run first, the first
+/// group in a batch of its own, which tells whether the enclave counts
+/// cycles. If it does, the rest runs in one batch, and the latency of a
+/// group is the sum of its cycles. If it does not, every group runs in a
+/// batch of its own (and the operations between groups in others), so that
+/// each group still gets a host time of its own.
+pub fn execute_groups<B: EnclaveBackend>(
+    backend: &B,
+    session_id: u64,
+    ops: &[Op],
+    groups: &[Range<usize>],
+) -> Result<(Vec<OpResult>, Latencies), BenchError> {
+    let mut end = 0;
+    for group in groups {
+        if group.start < end || group.end < group.start || group.end > ops.len() {
+            return Err(BenchError::Osm(OsmStatus::InvalidInput));
+        }
+        end = group.end;
+    }
+
+    let mut output = BatchOutput { results: Vec::with_capacity(ops.len()), cycles: Vec::new(), host_secs: 0.0 };
+    let mut host_secs = Vec::new();
+    let mut next = 0;
+    for group in groups {
+        if next < group.start {
+            output.append(execute_batch(backend, session_id, &ops[next..group.start])?);
+        }
+        let group_output = execute_batch(backend, session_id, &ops[group.clone()])?;
+        let timed = timing::is_timed(&group_output.cycles);
+        host_secs.push(group_output.host_secs);
+        output.append(group_output);
+        next = group.end;
+        if timed && group.start < group.end {
+            break;
+        }
+    }
+    if next < ops.len() {
+        output.append(execute_batch(backend, session_id, &ops[next..])?);
+    }
+
+    let latencies = if output.cycles.is_empty() || timing::is_timed(&output.cycles) {
+        output.group_latencies(groups.iter().cloned())
+    } else {
+        Latencies::host(host_secs)
+    };
+    Ok((output.results, latencies))
+}
+
+/// Inserts `n_keys` pairs in a single ECALL. The one sample is the host
+/// time of that ECALL per inserted key.
+pub fn insert_many<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, init_size: usize, n_keys: usize) -> Result<Latencies, BenchError> {
+    progress!(
         "\n[+] Size: {}, Number of keys: {}",
         init_size, n_keys
     );
     let mut map = Vec::with_capacity(n_keys);
     for _ in 0..init_size {
-        let key = rand::random::<Key>();
-        let value = rand::random::<Value>();
+        let key = rng.gen::<Key>();
+        let value = rng.gen::<Value>();
         map.push((key, value));
 
     }
-    println!("[+] Done with map");
+    progress!("[+] Done with map");
 
-    let (osm_client, mut server) =
-        STDOsmClient::<Key, Value, PathDOramClient<U160>>::setup(map.len() * 2, map)
-            .unwrap();
-    println!("[+] Done with setup");
+    let (osm_client, mut server) = setup_osm(backend, map.len() * 2, map);
+    progress!("[+] Done with setup");
 
     let mut keys = Vec::with_capacity(n_keys);
     let mut vals = Vec::with_capacity(n_keys);
     for _ in 0..n_keys {
-        let key = rand::random::<Key>();
-        let val = rand::random::<Value>();
+        let key = rng.gen::<Key>();
+        let val = rng.gen::<Value>();
         keys.push(key);
         vals.push(val);
     }
 
-    // let mut rng = OsRng::new().unwrap();
-    // let read_key = rng.choose(&keys).unwrap();
-    // Stash warm-up
-    // for _ in 0..30000 {
-        // let _ = osm_client.search(&read_key, 0, 1, &mut server);
-    // }
-
-
     // *****
     // *****
     // *****
     // Part inside here should be executed in the enclave.
-    let osm_client_ref = &osm_client as *const STDOsmClient<_, _, _> as u64;
-    let server_ref = &mut server as *mut LocalServer<PathDOramClient<U160>> as u64;
+    let session_id = open_session(backend, &osm_client, &mut server)?;
 
-    //println!("Loaded enclave.");
     let read_start = time::precise_time_s();
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        osm_insert_many(
-            enclave.geteid(),
-            &mut retval,
-            osm_client_ref as usize,
-            server_ref as usize,
-            keys.as_ptr() as u64 as usize,
-            keys.len(),
-            vals.as_ptr() as u64 as usize,
-            vals.len(),
-        )
-    };
-
+    let result = backend.osm_insert_many(session_id, &keys, &vals);
     let read_stop = time::precise_time_s();
     let avg_time = (read_stop - read_start) / n_keys as f64;
-    println!(
+    progress!(
         "[+] Capacity: {}, Inserted keys: {}, avg. time (s): {}",
         init_size * 2,
 	    n_keys,
         avg_time
     );
-    result
+    backend.osm_close(session_id)?;
+    result.map(|_| Latencies::host(vec![avg_time]))
     // *****
     // *****
     // *****
 }
 
-pub fn insert_one(enclave: &SgxEnclave, init_size: usize, n_keys: usize) -> sgx_status_t {
-    println!(
+pub fn insert_one<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, init_size: usize, n_keys: usize) -> Result<Latencies, BenchError> {
+    progress!(
         "\n[+] Size: {}, Number of keys: {}",
         init_size, n_keys
     );
     let mut map = Vec::with_capacity(init_size);
     for _ in 0..init_size {
-        let key = rand::random::<Key>();
-        let value = rand::random::<Value>();
+        let key = rng.gen::<Key>();
+        let value = rng.gen::<Value>();
         map.push((key, value));
 
     }
-    println!("Done with map");
+    progress!("Done with map");
 
-    let (osm_client, mut server) =
-        STDOsmClient::<Key, Value, PathDOramClient<U160>>::setup(init_size, map)
-            .unwrap();
-    println!("Done with setup");
+    let (osm_client, mut server) = setup_osm(backend, init_size, map);
+    progress!("Done with setup");
 
     let mut keys = Vec::with_capacity(n_keys);
     let mut vals = Vec::with_capacity(n_keys);
     for _ in 0..n_keys {
-        let key = rand::random::<Key>();
-        let val = rand::random::<Value>();
+        let key = rng.gen::<Key>();
+        let val = rng.gen::<Value>();
         keys.push(key);
         vals.push(val);
     }
 
-    // let mut rng = OsRng::new().unwrap();
-    // let read_key = rng.choose(&keys).unwrap();
-    // Stash warm-up
-    // for _ in 0..30000 {
-    //     let _ = osm_client.search(&read_key, 0, 1, &mut server);
-    // }
-
-
     // *****
     // *****
     // *****
     // Part inside here should be executed in the enclave.
-    let osm_client_ref = &osm_client as *const STDOsmClient<_, _, _> as usize;
-    let server_ref = &mut server as *mut LocalServer<PathDOramClient<U160>> as usize;
+    let session_id = open_session(backend, &osm_client, &mut server)?;
     let mut times = Vec::<f64>::with_capacity(n_keys);
-    let mut result = sgx_status_t::SGX_SUCCESS;
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    for (k, v) in keys.iter().zip(vals.iter()) {
-        let key_ref = k as *const Key as u64;
-        let val_ref = v as *const Value as u64;
-
-        //println!("Loaded enclave.");
+    let mut result = Ok(());
+    for (&k, &v) in keys.iter().zip(vals.iter()) {
         let read_start = time::precise_time_s();
-        //println!("Started reading");
-        result = unsafe { osm_insert_one(
-            enclave.geteid(),
-            &mut retval,
-            osm_client_ref,
-            server_ref,
-            key_ref as usize,
-            val_ref as usize,
-        )};
+        result = backend.osm_insert_one(session_id, k, v);
         let read_stop = time::precise_time_s();
+        if result.is_err() {
+            break;
+        }
         times.push(read_stop - read_start);
     }
-    println!(
+    progress!(
         "\nSize: {}, Keys: {}, times (s): {:?}",
         init_size, n_keys, times
     );
+    backend.osm_close(session_id)?;
     // *****
     // *****
     // *****
-    result
+    result.map(|_| Latencies::host(times))
 }
 
-pub fn delete_one(enclave: &SgxEnclave, init_size: usize, n_keys: usize) -> sgx_status_t {
+pub fn delete_one<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, init_size: usize, n_keys: usize) -> Result<Latencies, BenchError> {
 
     let mut map = Vec::with_capacity(init_size);
     let mut keys = Vec::with_capacity(n_keys);
     let mut vals = Vec::with_capacity(n_keys);
     for _ in 0..init_size {
-        let key = rand::random::<Key>();
-        let value = rand::random::<Value>();
+        let key = rng.gen::<Key>();
+        let value = rng.gen::<Value>();
         map.push((key.clone(), value.clone()));
         if keys.len() < n_keys {
             keys.push(key);
             vals.push(value);
         }
     }
-    println!("Done with map");
-
-    let (osm_client, mut server) =
-        STDOsmClient::<Key, Value, PathDOramClient<U160>>::setup(init_size, map)
-            .unwrap();
-    println!("Done with setup");
+    progress!("Done with map");
 
-    // let mut rng = OsRng::new().unwrap();
-    // let read_key = rng.choose(&keys).unwrap();
-    // Stash warm-up
-    // for _ in 0..30000 {
-    //     let _ = osm_client.search(&read_key, 0, 1, &mut server);
-    // }
+    let (osm_client, mut server) = setup_osm(backend, init_size, map);
+    progress!("Done with setup");
 
     // *****
     // *****
     // *****
     // Part inside here should be executed in the enclave.
-    let osm_client_ref = &osm_client as *const STDOsmClient<_, _, _> as u64;
-    let server_ref = &mut server as *mut LocalServer<PathDOramClient<U160>> as u64;
+    let session_id = open_session(backend, &osm_client, &mut server)?;
     let mut times = Vec::<f64>::with_capacity(n_keys);
-    let mut result = sgx_status_t::SGX_SUCCESS;
-    for (k, v) in keys.iter().zip(vals.iter()) {
-        let key_ref = k as *const Key as u64;
-        let val_ref = v as *const Value as u64;
-
-        println!("Loaded enclave.");
-        let mut retval = sgx_status_t::SGX_SUCCESS;
+    let mut result = Ok(());
+    for (&k, &v) in keys.iter().zip(vals.iter()) {
+        progress!("Loaded enclave.");
         let read_start = time::precise_time_s();
-        result = unsafe {
-            osm_delete_one(
-                enclave.geteid(),
-                &mut retval,
-                osm_client_ref as usize,
-                server_ref as usize,
-                key_ref as usize,
-                val_ref as usize,
-            )
-        };
+        result = backend.osm_delete_one(session_id, k, v);
         let read_stop = time::precise_time_s();
+        if result.is_err() {
+            break;
+        }
         times.push(read_stop - read_start);
     }
-    println!(
+    progress!(
         "\nSize: {}, Keys: {}, times (s): {:?}",
         init_size, n_keys, times
     );
-    result
+    backend.osm_close(session_id)?;
+    result.map(|_| Latencies::host(times))
     // *****
     // *****
     // *****
 }
 
-pub fn search(enclave: &SgxEnclave, n_keys: usize, vals_per_key: usize, range: usize) -> sgx_status_t {
-    println!(
+pub fn search<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    distribution: Distribution,
+    n_keys: usize,
+    vals_per_key: usize,
+    range: usize,
+) -> Result<Latencies, BenchError> {
+    progress!(
         "\n[+] Size: {}, Values per key: {}, range: {}",
         n_keys, vals_per_key, range
     );
 
-    let mut all_keys = Vec::with_capacity(n_keys);
+    let mut keys = Vec::with_capacity(n_keys);
     let mut map = Vec::<(Key, Value)>::with_capacity(n_keys * vals_per_key);
     for _ in 0..n_keys {
-        let key = rand::random::<Key>();
+        let key = rng.gen::<Key>();
+        keys.push(key);
         for _ in 0..vals_per_key {
-            let value = rand::random::<Value>();
-            all_keys.push(key);
+            let value = rng.gen::<Value>();
             map.push((key, value));
         }
     }
-    println!("[+] Done with map");
+    progress!("[+] Done with map");
 
-    let mut rng = OsRng::new().unwrap();
-    let read_key: &u64 = rng.choose(&all_keys).unwrap();
+    let num_reads: usize = 2000;
+    let read_keys: Vec<Key> = distribution.sample(rng, keys.len(), num_reads)
+        .into_iter()
+        .map(|i| keys[i])
+        .collect();
+    let mut expected: HashMap<Key, HashSet<Value>> = read_keys.iter().map(|&k| (k, HashSet::new())).collect();
+    for &(k, v) in map.iter() {
+        if let Some(values) = expected.get_mut(&k) {
+            values.insert(v);
+        }
+    }
 
     let l = map.len();
-    let (osm_client, mut server) =
-        STDOsmClient::<Key, Value, PathDOramClient<U160>>::setup(map.len(), map)
-            .unwrap();
-    println!("[+] Done with setup: {}", l);
-
-    // Stash warm-up
-    // for _ in 0..30000 {
-    //     let _ = osm_client.search(&read_key, 0, 1, &mut server);
-    // }
-    // println!("[+] Done with warm-up");
+    let (osm_client, mut server) = setup_osm(backend, map.len(), map);
+    progress!("[+] Done with setup: {}", l);
 
     // *****
     // *****
     // *****
     // Part inside here should be executed in the enclave.
-    let osm_client_ref = &osm_client as *const STDOsmClient<_, _, _> as u64;
-    let server_ref = &mut server as *mut LocalServer<PathDOramClient<U160>> as u64;
-    let key_ref = read_key as *const Key as u64;
-
-    let num_reads: usize = 2000;
-
-    let read_start = time::precise_time_s();
+    let session_id = open_session(backend, &osm_client, &mut server)?;
 
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        osm_search(
-            enclave.geteid(),
-            &mut retval,
-            osm_client_ref as usize,
-            server_ref as usize,
-            key_ref as usize,
-            range,
-        )
-    };
+    let ops: Vec<Op> = read_keys.iter().map(|&key| Op::Search { key, offset: 0, range }).collect();
 
-    let read_stop = time::precise_time_s();
-    let avg_time = (read_stop - read_start) / (num_reads as f64);
+    let result = execute_groups(backend, session_id, &ops, &batch::singles(ops.len())).and_then(|(results, latencies)| {
+        for (r, key) in results.iter().zip(read_keys.iter()) {
+            let values = r.clone().map_err(BenchError::Osm)?;
+            verify_search(&values, &expected[key], range)?;
+        }
+        Ok(latencies)
+    });
+    backend.osm_close(session_id)?;
+    let latencies = result?;
 
-    println!(
+    progress!(
         "[+] Size: {}, Values per key: {}, range: {}, times (s): {:?}",
-        n_keys, vals_per_key, range, avg_time
+        n_keys, vals_per_key, range, latencies.host_mean()
     );
+    progress!("[+] {} clock avg. time (s): {:?}", latencies.clock, latencies.mean());
 
-    result
+    Ok(latencies)
     // *****
     // *****
     // *****
 }
 
-pub fn zerotrace(enclave: &SgxEnclave, n_keys: usize) -> sgx_status_t {
+/// Runs an even mix of searches, inserts, deletes and updates against a
+/// storage of `init_size` pairs, batched (see `execute_groups`).
+pub fn mixed<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, init_size: usize, n_ops: usize) -> Result<Latencies, BenchError> {
+    progress!(
+        "\n[+] Size: {}, Number of operations: {}",
+        init_size, n_ops
+    );
+    let mut map = Vec::with_capacity(init_size);
+    for _ in 0..init_size {
+        let key = rng.gen::<Key>();
+        let value = rng.gen::<Value>();
+        map.push((key, value));
+    }
+    progress!("[+] Done with map");
+
+    // Deletes and updates target distinct existing pairs, so that every
+    // operation in the batch is expected to succeed.
+    let mut existing = map.clone();
+    rng.shuffle(&mut existing);
+    let mut ops = Vec::with_capacity(n_ops);
+    for i in 0..n_ops {
+        let (key, value) = existing[i % existing.len()];
+        ops.push(match i % 4 {
+            0 => Op::Search { key, offset: 0, range: 1 },
+            1 => Op::Insert { key: rng.gen::<Key>(), value: rng.gen::<Value>() },
+            2 => Op::Delete { key, value },
+            _ => Op::Update { key, old: value, new: rng.gen::<Value>() },
+        });
+    }
+
+    let (osm_client, mut server) = setup_osm(backend, init_size * 2, map);
+    progress!("[+] Done with setup");
+
+    let session_id = open_session(backend, &osm_client, &mut server)?;
+
+    let result = execute_groups(backend, session_id, &ops, &batch::singles(ops.len())).map(|(results, latencies)| {
+        let failed = results.iter().filter(|r| r.is_err()).count();
+        if failed > 0 {
+            progress!("[-] {} of {} operations failed", failed, n_ops);
+        }
+        latencies
+    });
+    backend.osm_close(session_id)?;
+    let latencies = result?;
+    progress!("[+] {} clock avg. time (s): {}", latencies.clock, latencies.mean());
+    progress!(
+        "[+] Size: {}, Operations: {}, avg. time (s): {}",
+        init_size, n_ops, latencies.host_mean()
+    );
+    Ok(latencies)
+}
+
+/// Runs `n_threads` clients concurrently, each with its own session over a
+/// storage of `init_size` pairs, issuing `n_ops` single-search ECALLs.
+/// Reports per-thread latency and aggregate throughput.
+pub fn throughput<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, n_threads: usize, init_size: usize, n_ops: usize) -> Result<Latencies, BenchError> {
+    progress!(
+        "\n[+] Threads: {}, Size: {}, Operations per thread: {}",
+        n_threads, init_size, n_ops
+    );
+
+    // Sessions reference their servers, so the servers are kept here until
+    // every thread has finished.
+    let mut servers = Vec::with_capacity(n_threads);
+    let mut clients = Vec::with_capacity(n_threads);
+    for _ in 0..n_threads {
+        let mut map = Vec::with_capacity(init_size);
+        for _ in 0..init_size {
+            map.push((rng.gen::<Key>(), rng.gen::<Value>()));
+        }
+        let keys: Vec<Key> = map.iter().map(|&(k, _)| k).collect();
+        let (osm_client, server) = setup_osm(backend, init_size, map);
+        servers.push(Box::new(server));
+        clients.push((osm_client, keys));
+    }
+    progress!("[+] Done with setup");
+
+    let mut sessions = Vec::with_capacity(n_threads);
+    for (&(ref osm_client, ref keys), server) in clients.iter().zip(servers.iter_mut()) {
+        match open_session(backend, osm_client, server) {
+            Ok(session_id) => sessions.push((session_id, keys.clone())),
+            Err(e) => {
+                let _ = close_sessions(backend, &sessions);
+                return Err(e);
+            },
+        }
+    }
+
+    let barrier = Arc::new(Barrier::new(n_threads));
+    let handles: Vec<_> = sessions.iter().cloned().map(|(session_id, keys)| {
+        let barrier = barrier.clone();
+        let backend = backend.clone();
+        let thread_seed = rng.gen::<u64>();
+        thread::spawn(move || -> Result<(Vec<f64>, f64), BenchError> {
+            let mut rng = workload_rng(thread_seed);
+            let mut latencies = Vec::with_capacity(n_ops);
+            let mut values = [0; 1];
+            barrier.wait();
+            let start = time::precise_time_s();
+            for _ in 0..n_ops {
+                let key = *rng.choose(&keys).unwrap();
+                let op_start = time::precise_time_s();
+                let result = backend.osm_search(session_id, key, 1, &mut values);
+                latencies.push(time::precise_time_s() - op_start);
+                result?;
+            }
+            Ok((latencies, time::precise_time_s() - start))
+        })
+    }).collect();
+
+    let mut result = Ok(());
+    let mut all_latencies = Vec::with_capacity(n_threads * n_ops);
+    let mut wall_time = 0f64;
+    for (i, handle) in handles.into_iter().enumerate() {
+        match handle.join().unwrap_or(Err(BenchError::ThreadPanicked)) {
+            Ok((latencies, elapsed)) => {
+                let avg = latencies.iter().sum::<f64>() / latencies.len() as f64;
+                progress!(
+                    "[+] Thread {}: avg. latency (s): {}, ops/sec: {}",
+                    i, avg, latencies.len() as f64 / elapsed
+                );
+                all_latencies.extend(latencies);
+                wall_time = wall_time.max(elapsed);
+            },
+            Err(e) => {
+                progress!("[-] Thread {} failed: {}", i, e);
+                result = Err(e);
+            },
+        }
+    }
+    progress!(
+        "[+] Threads: {}, Size: {}, aggregate ops/sec: {}",
+        n_threads, init_size, all_latencies.len() as f64 / wall_time
+    );
+
+    let closed = close_sessions(backend, &sessions);
+    result.and(closed).map(|_| Latencies::host(all_latencies))
+}
+
+/// Closes every session, even after one fails to close, and returns the
+/// first error.
+fn close_sessions<B: EnclaveBackend, T>(backend: &B, sessions: &[(u64, T)]) -> Result<(), BenchError> {
+    let mut result = Ok(());
+    for &(session_id, _) in sessions {
+        result = result.and(backend.osm_close(session_id));
+    }
+    result
+}
 
-    pretty_env_logger::init().unwrap();
+/// Sets up a storage of `init_size` pairs and saves it: the client state
+/// is sealed by the enclave and written to `<path>.sealed`, while the server
+/// (whose contents are already encrypted) and the dataset keys are written
+/// to `<path>.server`.
+pub fn save<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, init_size: usize, path: &str) -> Result<Latencies, BenchError> {
+    let mut map = Vec::with_capacity(init_size);
+    for _ in 0..init_size {
+        map.push((rng.gen::<Key>(), rng.gen::<Value>()));
+    }
+    let keys: Vec<Key> = map.iter().map(|&(k, _)| k).collect();
+
+    let setup_start = time::precise_time_s();
+    let (osm_client, mut server) = setup_osm(backend, init_size, map);
+    progress!("[+] Done with setup, time (s): {}", time::precise_time_s() - setup_start);
+
+    let session_id = open_session(backend, &osm_client, &mut server)?;
+    // Start with a guess; the enclave reports the actual size if it is
+    // too small.
+    let mut sealed = vec![0u8; 1 << 20];
+    let mut sealed_len = 0;
+    let seal_start = time::precise_time_s();
+    loop {
+        match backend.osm_seal(session_id, &mut sealed, &mut sealed_len) {
+            Err(BenchError::Osm(OsmStatus::BufferTooSmall)) if sealed.len() < sealed_len => {
+                sealed.resize(sealed_len, 0);
+            },
+            r => {
+                r?;
+                break;
+            },
+        }
+    }
+    let seal_time = time::precise_time_s() - seal_start;
+    backend.osm_close(session_id)?;
+
+    fs::write(format!("{}.sealed", path), &sealed[..sealed_len])?;
+    let server_file = fs::File::create(format!("{}.server", path))?;
+    bincode::serialize_into(io::BufWriter::new(server_file), &(&server, &keys))
+        .map_err(|_| BenchError::Io(io::ErrorKind::InvalidData))?;
+    progress!("[+] Saved {} pairs to {}.{{sealed,server}}, seal time (s): {}", init_size, path, seal_time);
+    Ok(Latencies::host(vec![seal_time]))
+}
+
+/// Restores a storage saved by `save` and runs `n_searches` searches on it.
+pub fn resume<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, path: &str, n_searches: usize) -> Result<Latencies, BenchError> {
+    let load_start = time::precise_time_s();
+    let sealed = fs::read(format!("{}.sealed", path))?;
+    let server_file = fs::File::open(format!("{}.server", path))?;
+    let (mut server, keys): (LocalServer<PathDOramClient<U160>>, Vec<Key>) =
+        bincode::deserialize_from(io::BufReader::new(server_file))
+            .map_err(|_| BenchError::Io(io::ErrorKind::InvalidData))?;
+    let load_stop = time::precise_time_s();
+    if keys.is_empty() {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+
+    let server_ref = &mut server as *mut LocalServer<PathDOramClient<U160>> as usize;
+    let unseal_start = time::precise_time_s();
+    let session_id = backend.osm_unseal(&sealed, server_ref)?;
+    let unseal_stop = time::precise_time_s();
+    progress!(
+        "[+] Size: {}, load time (s): {}, unseal time (s): {}",
+        keys.len(), load_stop - load_start, unseal_stop - unseal_start
+    );
+
+    let ops: Vec<Op> = (0..n_searches)
+        .map(|_| Op::Search { key: *rng.choose(&keys).unwrap(), offset: 0, range: 1 })
+        .collect();
+    let result = execute_groups(backend, session_id, &ops, &batch::singles(ops.len())).and_then(|(results, latencies)| {
+        for r in results.iter() {
+            if r.clone().map_err(BenchError::Osm)?.len() != 1 {
+                return Err(BenchError::IncorrectResult);
+            }
+        }
+        Ok(latencies)
+    });
+    backend.osm_close(session_id)?;
+    let latencies = result?;
+    progress!(
+        "[+] Size: {}, Searches: {}, avg. time (s): {}",
+        keys.len(), n_searches, latencies.host_mean()
+    );
+    Ok(latencies)
+}
+
+/// Checks that a search returned `min(range, |expected|)` distinct values,
+/// all of which were inserted under the queried key.
+pub fn verify_search(values: &[Value], expected: &HashSet<Value>, range: usize) -> Result<(), BenchError> {
+    let returned: HashSet<Value> = values.iter().cloned().collect();
+    let wanted = ::std::cmp::min(range, expected.len());
+    if values.len() == wanted && returned.len() == wanted && returned.is_subset(expected) {
+        Ok(())
+    } else {
+        progress!(
+            "[-] Expected {} values for the key, got {} ({} not in the dataset)",
+            wanted, values.len(), returned.difference(expected).count()
+        );
+        Err(BenchError::IncorrectResult)
+    }
+}
+
+pub fn zerotrace<B: EnclaveBackend>(backend: &B, rng: &mut WorkloadRng, distribution: Distribution, n_keys: usize) -> Result<Latencies, BenchError> {
+
+    // Trials call this more than once.
+    let _ = pretty_env_logger::init();
     let (mut client, mut server): (PathDOramClient<U8>, LocalServer<PathDOramClient<U8>>) =
         setup_oram(n_keys as u64);
-    println!("After server setup!");
+    progress!("After server setup!");
 
     let mut keys_and_positions = vec![];
 
     let num_reads: usize = 1000;
-    for _ in 0..num_reads {
-        let random_key = OramKey::rand() % (n_keys as u64);
+    for i in distribution.sample(rng, n_keys, num_reads) {
+        let random_key = OramKey::new(i as u64);
         let pos = client.position_for_key(random_key);
 
         assert!(pos != NoPos);
@@ -384,77 +638,76 @@ pub fn zerotrace(enclave: &SgxEnclave, n_keys: usize) -> sgx_status_t {
     // *****
     // *****
     // Part inside here should be executed in the enclave.
-    let client_ref = &client as *const PathDOramClient<_, _, _> as u64;
+    let client = marshal::encode_state(&client, StateKind::DOram, 8);
     let server_ref = &mut server as *mut LocalServer<PathDOramClient<U8>> as u64;
-    let key_and_pos_ref = keys_and_positions.as_slice().as_ptr() as u64;
-    let key_and_pos_len = keys_and_positions.len();
+    let key_and_pos = marshal::encode(&keys_and_positions);
 
+    let mut cycles = vec![0; num_reads];
     let read_start = time::precise_time_s();
-    let mut result = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe { oram_zerotrace(
-        enclave.geteid(),
-        &mut result,
-        client_ref as usize,
-        server_ref as usize,
-        key_and_pos_ref as usize,
-        key_and_pos_len,
-    ) };
+    let result = backend.oram_zerotrace(&client, server_ref as usize, &key_and_pos, &mut cycles);
 
     let read_stop = time::precise_time_s();
     let avg_time = (read_stop - read_start) / num_reads as f64;
+    let latencies = Latencies::from_cycles(&cycles, read_stop - read_start);
 
-    println!(
-        "\nSize: {}, time (s): {:?}",
-        n_keys, avg_time
+    progress!(
+        "\nSize: {}, time (s): {:?}, {} time (s): {:?}",
+        n_keys, avg_time, latencies.clock, latencies.mean()
     );
-    result
+    result.map(|_| latencies)
     // *****
     // *****
     // *****
 }
 
-pub fn doram(enclave: &SgxEnclave, n_keys: usize, block_size: u64) -> sgx_status_t {
+pub fn doram<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    distribution: Distribution,
+    n_keys: usize,
+    block_size: u64,
+) -> Result<Latencies, BenchError> {
+    const NUM_READS: usize = 1000;
+    let keys: Vec<u64> = distribution.sample(rng, n_keys, NUM_READS).into_iter().map(|i| i as u64).collect();
+    doram_reads(backend, n_keys, block_size, &keys)
+}
+
+/// Sets up an ORAM of `n_keys` blocks of `block_size` bytes and reads
+/// `keys` from it, in order, in a single ECALL.
+pub fn doram_reads<B: EnclaveBackend>(backend: &B, n_keys: usize, block_size: u64, keys: &[u64]) -> Result<Latencies, BenchError> {
 
-    pretty_env_logger::init().unwrap();
-    const NUM_READS: u64  = 1000;
+    // Trials call this more than once.
+    let _ = pretty_env_logger::init();
 
-    fn run_in_enclave(
-        enclave: &SgxEnclave,
-        client_ref: u64,
+    fn run_in_enclave<B: EnclaveBackend>(
+        backend: &B,
+        client: &[u8],
         server_ref: u64,
-        key_and_pos_ref: u64,
-        key_and_pos_len: usize,
+        key_and_pos: &[u8],
         n_keys: usize,
+        n_reads: usize,
         block_size: u64
-    ) -> sgx_status_t {
+    ) -> Result<Latencies, BenchError> {
         // *****
         // *****
         // *****
         // Part inside here should be executed in the enclave.
+        let mut cycles = vec![0; n_reads];
         let read_start = time::precise_time_s();
 
-        let ret = unsafe { 
-            oram_access(
-                enclave.geteid(),
-                &mut sgx_status_t::SGX_SUCCESS,
-                client_ref as usize,
-                server_ref as usize,
-                key_and_pos_ref as usize,
-                key_and_pos_len,
-                block_size as usize,
-            )
-        };
+        let result = backend.oram_access(client, server_ref as usize, key_and_pos, block_size as usize, &mut cycles);
         let read_stop = time::precise_time_s();
-        let avg_time = (read_stop - read_start) / NUM_READS as f64;
+        let avg_time = (read_stop - read_start) / n_reads as f64;
+        let latencies = Latencies::from_cycles(&cycles, read_stop - read_start);
 
-        println!(
-            "\nItems: {}, Blocksize: {}, time (s): {:?}",
-            n_keys, block_size, avg_time
+        progress!(
+            "\nItems: {}, Blocksize: {}, time (s): {:?}, {} time (s): {:?}",
+            n_keys, block_size, avg_time, latencies.clock, latencies.mean()
         );
         // *****
         // *****
         // *****
-        ret
+        result.map(|_| latencies)
     }
 
     macro_rules! run_experiment_for_block_size {
@@ -462,21 +715,20 @@ pub fn doram(enclave: &SgxEnclave, n_keys: usize, block_size: u64) -> sgx_status
             {
                 let (mut client, mut server): (PathDOramClient<$type_n>, LocalServer<PathDOramClient<$type_n>>) =
                                                setup_oram(n_keys as u64);
-                println!("After server setup!");
+                progress!("After server setup!");
                 let mut keys_and_positions = vec![];
-                for _ in 0..NUM_READS {
-                    let random_key = OramKey::rand() % (n_keys as u64);
+                for &key in keys {
+                    let random_key = OramKey::new(key);
                     let pos = client.position_for_key(random_key);
 
                     assert!(pos != NoPos);
                     keys_and_positions.push((random_key, pos));
                 }
                 client.pos_map = PositionMap::new(0);
-                let client_ref = &client as *const PathDOramClient<_, _, _> as u64;
+                let client = marshal::encode_state(&client, StateKind::DOram, $n);
                 let server_ref = &mut server as *mut LocalServer<PathDOramClient<$type_n>> as u64;
-                let key_and_pos_ref = &keys_and_positions as *const Vec<(OramKey, OramPos)> as u64;
-                let key_and_pos_len = keys_and_positions.len();
-                run_in_enclave(enclave, client_ref, server_ref, key_and_pos_ref, key_and_pos_len, n_keys, $n)
+                let key_and_pos = marshal::encode(&keys_and_positions);
+                run_in_enclave(backend, &client, server_ref, &key_and_pos, n_keys, keys.len(), $n)
             }
         }
     }
@@ -486,9 +738,16 @@ pub fn doram(enclave: &SgxEnclave, n_keys: usize, block_size: u64) -> sgx_status
         32 => run_experiment_for_block_size!(32, U32),
         64 => run_experiment_for_block_size!(64, U64),
         128 => run_experiment_for_block_size!(128, U128),
+        160 => run_experiment_for_block_size!(160, U160),
         256 => run_experiment_for_block_size!(256, U256),
         512 => run_experiment_for_block_size!(512, U512),
-        _   => panic!("Block size not supported, please input a block_size in the range {8, 16, 32, ..., 512}")
+        1024 => run_experiment_for_block_size!(1024, U1024),
+        2048 => run_experiment_for_block_size!(2048, U2048),
+        4096 => run_experiment_for_block_size!(4096, U4096),
+        _   => {
+            progress!("[-] Block size not supported, please input one of {{8, 16, 32, ..., 512, 160, 1024, 2048, 4096}}");
+            Err(BenchError::Osm(OsmStatus::InvalidInput))
+        }
     }
 }
 
@@ -505,17 +764,6 @@ fn setup_oram<N, C, M>(
         C: Encryptor,
         M: MerkleTree,
 {
-    let (mut client, _) = PathDOramClient::new(num_items, vec![]);
-    let mut oram_data_map = Vec::with_capacity(num_items as usize);
-    for i in 0..num_items {
-        oram_data_map.push(
-            (
-                OramKey::new(i),
-                BlockContent::with_slice(&[(i as u8 % 128u8); 8]),
-            )
-        );
-    }
-    println!("Before oram local setup to read");
-    let server = client.local_setup(oram_data_map).unwrap();
+    let (client, server, _) = setup::oram_stages(num_items);
     (client, server)
 }
@@ -0,0 +1,188 @@
+//! Replay of operation traces (see `trace.rs`) through the enclave.
+
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+use rand::Rng;
+use time;
+
+use backend::EnclaveBackend;
+use batch::{self, Op};
+use errors::{BenchError, OsmStatus};
+use microbenchmarks::{self, Key, Value, WorkloadRng};
+use timing::Latencies;
+use trace::{TraceEntry, TraceOp};
+
+/// The pairs that must be added to `loaded` for every delete and update in
+/// `ops` to find the value it removes.
+fn preload(loaded: &[(Key, Value)], ops: &[Op]) -> Vec<(Key, Value)> {
+    let mut present: HashSet<(Key, Value)> = loaded.iter().cloned().collect();
+    let mut missing = Vec::new();
+    for op in ops {
+        match *op {
+            Op::Search { .. } => (),
+            Op::Insert { key, value } => {
+                present.insert((key, value));
+            },
+            Op::Delete { key, value } => {
+                if !present.remove(&(key, value)) {
+                    missing.push((key, value));
+                }
+            },
+            Op::Update { key, old, new } => {
+                if !present.remove(&(key, old)) {
+                    missing.push((key, old));
+                }
+                present.insert((key, new));
+            },
+        }
+    }
+    missing
+}
+
+/// Replays the OSM operations of a trace against a store holding the pairs
+/// the trace loads, `initial_size` random pairs, and whatever the trace
+/// deletes or updates without inserting first. A trace recorded from
+/// several sessions loads the pairs of all of them into the one store.
+///
+/// By default the whole trace is executed as a batch (see
+/// `microbenchmarks::execute_groups`) and the latencies are in-enclave
+/// times, or host times of one ECALL per operation if the enclave does not
+/// count cycles. With `realtime`, every operation is issued in its
+/// own ECALL at its timestamp, and the latencies are host times.
+/// Operations that fail inside the enclave (say, a search for a key that
+/// the log never inserted) are counted but do not stop the replay.
+pub fn osm<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    entries: &[TraceEntry],
+    initial_size: usize,
+    realtime: bool,
+) -> Result<Latencies, BenchError> {
+    let mut timed: Vec<(u64, Op)> = Vec::with_capacity(entries.len());
+    let mut loaded = Vec::new();
+    for entry in entries {
+        match entry.op {
+            TraceOp::Osm(op) => timed.push((entry.timestamp_ns, op)),
+            TraceOp::Load { key, value } => loaded.push((key, value)),
+            TraceOp::Access(_) => (),
+        }
+    }
+    if timed.len() + loaded.len() < entries.len() {
+        progress!("[-] Skipping {} ORAM accesses in the trace", entries.len() - timed.len() - loaded.len());
+    }
+    if timed.is_empty() {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+    let ops: Vec<Op> = timed.iter().map(|&(_, op)| op).collect();
+
+    let mut map = preload(&loaded, &ops);
+    let preloaded = loaded.len() + map.len();
+    map.extend(loaded);
+    for _ in 0..initial_size {
+        map.push((rng.gen::<Key>(), rng.gen::<Value>()));
+    }
+    progress!(
+        "\n[+] Operations: {}, Preloaded pairs: {} (from the trace: {})",
+        ops.len(), map.len(), preloaded
+    );
+
+    let inserts = ops.iter().filter(|op| match **op { Op::Search { .. } => false, _ => true }).count();
+    let capacity = ::std::cmp::max(map.len() + inserts, 1);
+    let (osm_client, mut server) = microbenchmarks::setup_osm(backend, capacity, map);
+    progress!("[+] Done with setup");
+
+    let session_id = microbenchmarks::open_session(backend, &osm_client, &mut server)?;
+    let replayed = if realtime {
+        replay_realtime(backend, session_id, &timed)
+    } else {
+        microbenchmarks::execute_groups(backend, session_id, &ops, &batch::singles(ops.len()))
+            .map(|(results, latencies)| {
+                let failed = results.iter().filter(|r| r.is_err()).count();
+                (latencies, failed)
+            })
+    };
+    backend.osm_close(session_id)?;
+    let (latencies, failed) = replayed?;
+
+    if failed > 0 {
+        progress!("[-] {} of {} operations failed", failed, ops.len());
+    }
+    progress!("[+] Operations: {}, avg. time (s): {}", ops.len(), latencies.mean());
+    Ok(latencies)
+}
+
+fn replay_realtime<B: EnclaveBackend>(
+    backend: &B,
+    session_id: u64,
+    timed: &[(u64, Op)],
+) -> Result<(Latencies, usize), BenchError> {
+    let mut latencies = Vec::with_capacity(timed.len());
+    let mut failed = 0;
+    let mut max_lag_ns = 0;
+    let first_ns = timed[0].0;
+    let start_ns = time::precise_time_ns();
+    for &(timestamp_ns, op) in timed {
+        let due_ns = start_ns + timestamp_ns.saturating_sub(first_ns);
+        let now_ns = time::precise_time_ns();
+        if now_ns < due_ns {
+            thread::sleep(Duration::from_nanos(due_ns - now_ns));
+        } else {
+            max_lag_ns = ::std::cmp::max(max_lag_ns, now_ns - due_ns);
+        }
+        let op_start = time::precise_time_s();
+        let output = microbenchmarks::execute_batch(backend, session_id, &[op])?;
+        latencies.push(time::precise_time_s() - op_start);
+        failed += output.results.iter().filter(|r| r.is_err()).count();
+    }
+    if max_lag_ns > 0 {
+        progress!("[-] Fell behind the trace by up to {} ms", max_lag_ns / 1_000_000);
+    }
+    Ok((Latencies::host(latencies), failed))
+}
+
+/// Replays the ORAM accesses of a trace against an ORAM of `n_keys` blocks
+/// of `block_size` bytes, grown if the trace reads past its end.
+pub fn oram<B: EnclaveBackend>(
+    backend: &B,
+    entries: &[TraceEntry],
+    n_keys: usize,
+    block_size: u64,
+) -> Result<Latencies, BenchError> {
+    let keys: Vec<u64> = entries.iter()
+        .filter_map(|entry| match entry.op {
+            TraceOp::Access(index) => Some(index),
+            TraceOp::Load { .. } | TraceOp::Osm(_) => None,
+        })
+        .collect();
+    if keys.len() < entries.len() {
+        progress!("[-] Skipping {} OSM operations in the trace", entries.len() - keys.len());
+    }
+    let n_keys = match keys.iter().max() {
+        Some(&max) => ::std::cmp::max(n_keys, max as usize + 1),
+        None => return Err(BenchError::Osm(OsmStatus::InvalidInput)),
+    };
+    progress!("\n[+] Accesses: {}, Blocks: {}", keys.len(), n_keys);
+    microbenchmarks::doram_reads(backend, n_keys, block_size, &keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preload_adds_only_what_the_trace_removes_first() {
+        let loaded = [(1, 10)];
+        let ops = [
+            Op::Delete { key: 1, value: 10 },
+            Op::Insert { key: 2, value: 20 },
+            Op::Delete { key: 2, value: 20 },
+            Op::Delete { key: 3, value: 30 },
+            Op::Update { key: 4, old: 40, new: 41 },
+            Op::Update { key: 4, old: 41, new: 42 },
+            Op::Search { key: 5, offset: 0, range: 1 },
+        ];
+        assert_eq!(preload(&loaded, &ops), vec![(3, 30), (4, 40)]);
+    }
+}
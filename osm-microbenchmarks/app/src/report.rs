@@ -0,0 +1,391 @@
+//! Output of benchmark results.
+//!
+//! Every parameter point produces one `Record`: what was run, with which
+//! parameters, the latency samples of all its trials, the clock they were
+//! read from and their summary, plus metadata about the host and the
+//! build. Records are printed as text for people, or as JSON (one object
+//! per line) or CSV for scripts.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde_json;
+use time;
+
+use distribution::Distribution;
+use errors::BenchError;
+use microbenchmarks::{self, WorkloadRng};
+use stats::Summary;
+use timing::{Clock, Latencies};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unknown output format `{}` (expected json, csv or text)", s)),
+        }
+    }
+}
+
+/// The parameters of a point in a sweep. Benchmarks set the ones that
+/// apply to them.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Params {
+    pub n_keys: Option<usize>,
+    pub vals_per_key: Option<usize>,
+    pub range: Option<usize>,
+    pub block_size: Option<usize>,
+    pub n_ops: Option<usize>,
+    pub n_threads: Option<usize>,
+    pub distribution: Option<Distribution>,
+    /// Items a setup stage processes; reported as items per second.
+    pub n_items: Option<usize>,
+    /// Keywords per query in multi-keyword searches.
+    pub n_terms: Option<usize>,
+    /// Seed of the point's workload generator; set when it is reported.
+    pub point_seed: Option<u64>,
+}
+
+impl fmt::Display for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields = [
+            ("keys", self.n_keys),
+            ("values per key", self.vals_per_key),
+            ("range", self.range),
+            ("block size", self.block_size),
+            ("operations", self.n_ops),
+            ("threads", self.n_threads),
+            ("items", self.n_items),
+            ("terms", self.n_terms),
+        ];
+        let mut first = true;
+        for &(name, value) in fields.iter() {
+            if let Some(value) = value {
+                write!(f, "{}{}: {}", if first { "" } else { ", " }, name, value)?;
+                first = false;
+            }
+        }
+        if let Some(distribution) = self.distribution {
+            write!(f, "{}distribution: {}", if first { "" } else { ", " }, distribution)?;
+            first = false;
+        }
+        if let Some(seed) = self.point_seed {
+            write!(f, "{}point seed: {}", if first { "" } else { ", " }, seed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where and how the results were produced.
+#[derive(Clone, Debug, Serialize)]
+pub struct Metadata {
+    pub backend: &'static str,
+    /// Seed of the run, from which every point derives its own; rerunning
+    /// with it reproduces the datasets and queries of the whole run.
+    pub seed: u64,
+    pub hostname: String,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub cpu: String,
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub profile: &'static str,
+    pub timestamp: String,
+}
+
+impl Metadata {
+    pub fn collect(backend: &'static str, seed: u64) -> Metadata {
+        Metadata {
+            backend,
+            seed,
+            hostname: read_trimmed("/proc/sys/kernel/hostname").unwrap_or_else(|| "unknown".to_string()),
+            os: ::std::env::consts::OS,
+            arch: ::std::env::consts::ARCH,
+            cpu: cpu_model().unwrap_or_else(|| "unknown".to_string()),
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("BUILD_GIT_COMMIT"),
+            profile: env!("BUILD_PROFILE"),
+            timestamp: time::now_utc().rfc3339().to_string(),
+        }
+    }
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn cpu_model() -> Option<String> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    cpuinfo.lines()
+        .find(|line| line.starts_with("model name"))
+        .and_then(|line| line.splitn(2, ':').nth(1))
+        .map(|model| model.trim().to_string())
+}
+
+#[derive(Serialize)]
+pub struct Record<'a> {
+    pub benchmark: &'a str,
+    #[serde(flatten)]
+    pub params: Params,
+    pub trials: usize,
+    /// What the samples were timed with; `None` if the benchmark failed.
+    pub clock: Option<Clock>,
+    /// `None` if the benchmark failed or measured nothing, and for the
+    /// `ecall` clock, whose samples are ECALL means rather than operations.
+    pub summary: Option<Summary>,
+    /// For enclave-clock samples, the mean host time of the ECALLs per
+    /// sample: the end-to-end cost next to the in-enclave one. For the
+    /// `ecall` clock, the only statistic reported: the mean host time per
+    /// operation.
+    pub host_mean: Option<f64>,
+    /// `n_items` over the mean latency, for setup stages.
+    pub items_per_sec: Option<f64>,
+    pub noisy: bool,
+    pub error: Option<String>,
+    /// Per-operation latencies of all trials, in seconds; per-ECALL means
+    /// for the `ecall` clock.
+    pub samples: &'a [f64],
+    pub metadata: &'a Metadata,
+}
+
+const CSV_HEADER: &'static str =
+    "benchmark,backend,n_keys,vals_per_key,range,block_size,n_ops,n_threads,distribution,n_items,n_terms,point_seed,trials,clock,\
+     samples,min,median,p90,p99,max,mean,std_dev,ci95_low,ci95_high,cv,host_mean,items_per_sec,noisy,error,\
+     seed,hostname,os,arch,cpu,version,git_commit,profile,timestamp,samples_s";
+
+pub struct Reporter {
+    format: OutputFormat,
+    out: Box<Write>,
+    metadata: Metadata,
+    wrote_header: bool,
+    /// Points started so far.
+    points: u64,
+    /// Used by every point instead of the seed it would derive.
+    point_seed: Option<u64>,
+}
+
+impl Reporter {
+    /// Writes to `path`, or to stdout if there is none.
+    pub fn new(
+        format: OutputFormat,
+        path: Option<&str>,
+        metadata: Metadata,
+        point_seed: Option<u64>,
+    ) -> io::Result<Reporter> {
+        let out: Box<Write> = match path {
+            Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+            None => Box::new(io::stdout()),
+        };
+        Ok(Reporter { format, out, metadata, wrote_header: false, points: 0, point_seed })
+    }
+
+    /// Starts the next point: returns its seed and a generator seeded with
+    /// it.
+    pub fn next_point(&mut self) -> (u64, WorkloadRng) {
+        let seed = self.point_seed.unwrap_or_else(|| microbenchmarks::point_seed(self.metadata.seed, self.points));
+        self.points += 1;
+        (seed, microbenchmarks::workload_rng(seed))
+    }
+
+    /// Runs a benchmark `trials` times, with the generator of a new point,
+    /// and reports the latencies it measured. Returns the number of
+    /// failures (0 or 1) to add to the running total; a failed trial, or
+    /// one timed with a different clock than the first, stops the remaining
+    /// ones.
+    pub fn measure<F>(&mut self, benchmark: &str, params: Params, trials: usize, mut run: F) -> usize
+        where F: FnMut(&mut WorkloadRng) -> Result<Latencies, BenchError>
+    {
+        let (seed, mut rng) = self.next_point();
+        let params = Params { point_seed: Some(seed), ..params };
+        let mut latencies: Option<Latencies> = None;
+        let mut error = None;
+        for trial in 0..trials {
+            let pooled = run(&mut rng).map_err(|e| e.to_string()).and_then(|l| {
+                if let Some(ref mut pooled) = latencies {
+                    return pooled.extend(l);
+                }
+                latencies = Some(l);
+                Ok(())
+            });
+            if let Err(e) = pooled {
+                error = Some(format!("trial {}: {}", trial + 1, e));
+                break;
+            }
+        }
+        let latencies = latencies.unwrap_or_else(|| Latencies::host(Vec::new()));
+        self.report(benchmark, params, trials, &latencies, error)
+    }
+
+    /// Reports latencies measured elsewhere, or the error that stopped
+    /// them. Returns the number of failures, as `measure` does.
+    pub fn report(
+        &mut self,
+        benchmark: &str,
+        params: Params,
+        trials: usize,
+        latencies: &Latencies,
+        error: Option<String>,
+    ) -> usize {
+        let failed = error.is_some() as usize;
+        if let Err(e) = self.record(benchmark, params, trials, latencies, error) {
+            progress!("[-] Could not write the results of {}: {}", benchmark, e);
+        }
+        failed
+    }
+
+    fn record(
+        &mut self,
+        benchmark: &str,
+        params: Params,
+        trials: usize,
+        latencies: &Latencies,
+        error: Option<String>,
+    ) -> io::Result<()> {
+        let clock = if error.is_none() { Some(latencies.clock) } else { None };
+        let summary = match clock {
+            Some(Clock::Enclave) | Some(Clock::Host) => Summary::new(trials, &latencies.secs),
+            Some(Clock::Ecall) | None => None,
+        };
+        let record = Record {
+            benchmark,
+            params,
+            trials,
+            clock,
+            summary,
+            host_mean: match (clock, summary) {
+                (Some(Clock::Enclave), Some(_)) => Some(latencies.host_mean()),
+                (Some(Clock::Ecall), _) if !latencies.secs.is_empty() => Some(latencies.host_mean()),
+                _ => None,
+            },
+            items_per_sec: match (params.n_items, summary) {
+                (Some(items), Some(summary)) if summary.mean > 0.0 => Some(items as f64 / summary.mean),
+                _ => None,
+            },
+            noisy: summary.map_or(false, |s| s.is_noisy()),
+            error,
+            samples: &latencies.secs,
+            metadata: &self.metadata,
+        };
+        match self.format {
+            OutputFormat::Text => write_text(&mut self.out, &record),
+            OutputFormat::Json => {
+                serde_json::to_writer(&mut self.out, &record)?;
+                writeln!(self.out)
+            },
+            OutputFormat::Csv => {
+                if !self.wrote_header {
+                    writeln!(self.out, "{}", CSV_HEADER)?;
+                    self.wrote_header = true;
+                }
+                write_csv(&mut self.out, &record)
+            },
+        }?;
+        self.out.flush()
+    }
+}
+
+fn write_text<W: Write>(out: &mut W, record: &Record) -> io::Result<()> {
+    let backend = record.metadata.backend;
+    match (&record.error, &record.summary) {
+        (&Some(ref e), _) => writeln!(out, "[-] {} ({}) [{}] failed: {}", record.benchmark, backend, record.params, e),
+        (&None, &Some(ref summary)) => {
+            write!(out, "[+] {} ({}) [{}]: {}", record.benchmark, backend, record.params, summary)?;
+            if let Some(clock) = record.clock {
+                write!(out, ", {} clock", clock)?;
+            }
+            if let Some(host_mean) = record.host_mean {
+                write!(out, ", end to end: {:e} (s)", host_mean)?;
+            }
+            if let Some(rate) = record.items_per_sec {
+                write!(out, ", items/s: {:.0}", rate)?;
+            }
+            writeln!(out)
+        },
+        (&None, &None) => match record.host_mean {
+            Some(mean) => writeln!(
+                out,
+                "[+] {} ({}) [{}]: trials: {}, mean per operation: {:e} (s), ecall clock (untimed enclave: \
+                 one sample per ECALL, no percentiles)",
+                record.benchmark, backend, record.params, record.trials, mean
+            ),
+            None => writeln!(out, "[-] {} ({}) [{}]: no samples", record.benchmark, backend, record.params),
+        },
+    }
+}
+
+fn write_csv<W: Write>(out: &mut W, record: &Record) -> io::Result<()> {
+    fn opt<T: ToString>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or_default()
+    }
+    fn quote(field: &str) -> String {
+        if field.contains(|c| c == ',' || c == '"' || c == '\n') {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    let p = &record.params;
+    let m = record.metadata;
+    let s = record.summary;
+    let mut fields = vec![
+        quote(record.benchmark),
+        m.backend.to_string(),
+        opt(p.n_keys),
+        opt(p.vals_per_key),
+        opt(p.range),
+        opt(p.block_size),
+        opt(p.n_ops),
+        opt(p.n_threads),
+        opt(p.distribution),
+        opt(p.n_items),
+        opt(p.n_terms),
+        opt(p.point_seed),
+        record.trials.to_string(),
+        opt(record.clock),
+    ];
+    fields.extend(vec![
+        opt(s.map(|s| s.samples)),
+        opt(s.map(|s| s.min)),
+        opt(s.map(|s| s.median)),
+        opt(s.map(|s| s.p90)),
+        opt(s.map(|s| s.p99)),
+        opt(s.map(|s| s.max)),
+        opt(s.map(|s| s.mean)),
+        opt(s.map(|s| s.std_dev)),
+        opt(s.map(|s| s.ci95.0)),
+        opt(s.map(|s| s.ci95.1)),
+        opt(s.map(|s| s.cv())),
+        opt(record.host_mean),
+        opt(record.items_per_sec),
+        record.noisy.to_string(),
+        quote(record.error.as_ref().map_or("", |e| e.as_str())),
+    ]);
+    fields.extend(vec![
+        m.seed.to_string(),
+        quote(&m.hostname),
+        m.os.to_string(),
+        m.arch.to_string(),
+        quote(&m.cpu),
+        m.version.to_string(),
+        m.git_commit.to_string(),
+        m.profile.to_string(),
+        m.timestamp.clone(),
+        // `;`-separated, so that the row keeps a fixed number of columns.
+        record.samples.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(";"),
+    ]);
+    writeln!(out, "{}", fields.join(","))
+}
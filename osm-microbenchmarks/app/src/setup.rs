@@ -0,0 +1,210 @@
+//! Timing of the setup that precedes every benchmark, broken into stages.
+//!
+//! The OSM stages are:
+//!
+//! ```text
+//! map        generating the key-value pairs
+//! osm_tree   STDOsmClient::setup: building the OSM tree, placing its nodes
+//!            in the ORAM, encrypting the blocks and filling the position
+//!            map, all in one call
+//! enclave    serializing the client and opening a session with it
+//! ```
+//!
+//! and the ORAM stages, which break down the ORAM that `osm_tree` builds:
+//!
+//! ```text
+//! blocks     generating the block contents
+//! client     PathDOramClient::new: allocating the client and its (still
+//!            empty) position map
+//! tree       local_setup: placing the blocks in the tree, encrypting them
+//!            and filling the position map
+//! ```
+//!
+//! The request also asked for encryption and the position-map build as
+//! stages of their own. They are not reported: `local_setup` (which
+//! `STDOsmClient::setup` calls) encrypts each block as it places it and
+//! fills the position map in the same pass, and `path_oram` exposes neither
+//! step on its own. Splitting them out needs a change to `path_oram` (for
+//! example a `local_setup` that returns its own stage timings); until then
+//! `osm_tree` and `tree` include both, and every run says so.
+//!
+//! Every stage is reported as its own benchmark (`setup::osm::map`, ...),
+//! with one sample per trial and the items per second it processed.
+
+use generic_array::ArrayLength;
+use generic_array::typenum::{U8, U16, U32, U64, U128, U160, U256, U512, U1024, U2048, U4096};
+use osm::STDOsmClient;
+use path_oram::{BlockContent, EncBlkSize, EncN, LocalServer, OramKey, PathDOramClient, TreeOramClient};
+use path_oram::oram_crypto::{Encryptor, MerkleTree};
+use rand::Rng;
+use time;
+
+use backend::EnclaveBackend;
+use errors::{BenchError, OsmStatus};
+use microbenchmarks::{self, Key, Value, WorkloadRng};
+use report::{Params, Reporter};
+use timing::Latencies;
+
+/// How long one stage of a setup took.
+#[derive(Clone, Copy, Debug)]
+pub struct Stage {
+    pub name: &'static str,
+    /// Pairs or blocks the stage processed.
+    pub items: usize,
+    pub secs: f64,
+}
+
+impl Stage {
+    fn timed<T, F: FnOnce() -> T>(name: &'static str, items: usize, f: F) -> (T, Stage) {
+        let start = time::precise_time_s();
+        let result = f();
+        let stage = Stage { name, items, secs: time::precise_time_s() - start };
+        progress!(
+            "[+] {}: {} items, time (s): {}, items/s: {:.0}",
+            name, items, stage.secs, items as f64 / stage.secs
+        );
+        (result, stage)
+    }
+}
+
+/// Says that `stage` also covers encryption and the position map, which
+/// cannot be timed on their own (see the module documentation).
+fn warn_combined_stage(stage: &str) {
+    progress!("[-] {} includes encrypting the blocks and filling the position map: path_oram cannot time them separately", stage);
+}
+
+/// Sets up an OSM of `n_keys` keys with `vals_per_key` values each and
+/// opens a session with it.
+pub fn osm<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    n_keys: usize,
+    vals_per_key: usize,
+) -> Result<Vec<Stage>, BenchError> {
+    progress!("\n[+] Keys: {}, Values per key: {}", n_keys, vals_per_key);
+    let n_pairs = n_keys * vals_per_key;
+    if n_pairs == 0 {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+
+    let (map, map_stage) = Stage::timed("map", n_pairs, || {
+        let mut map = Vec::with_capacity(n_pairs);
+        for _ in 0..n_keys {
+            let key = rng.gen::<Key>();
+            for _ in 0..vals_per_key {
+                map.push((key, rng.gen::<Value>()));
+            }
+        }
+        map
+    });
+    backend.osm_preload(&map);
+    warn_combined_stage("osm_tree");
+    let ((osm_client, mut server), tree_stage) = Stage::timed("osm_tree", n_pairs, || {
+        STDOsmClient::<Key, Value, PathDOramClient<U160>>::setup(n_pairs, map).unwrap()
+    });
+    let (session, enclave_stage) = Stage::timed("enclave", n_pairs, || {
+        microbenchmarks::open_session(backend, &osm_client, &mut server)
+    });
+    backend.osm_close(session?)?;
+    Ok(vec![map_stage, tree_stage, enclave_stage])
+}
+
+/// Sets up an ORAM of `n_keys` blocks of `block_size` bytes.
+pub fn oram(n_keys: usize, block_size: usize) -> Result<Vec<Stage>, BenchError> {
+    progress!("\n[+] Blocks: {}, Block size: {}", n_keys, block_size);
+    macro_rules! stages_for_block_size {
+        ($type_n:ty) => {
+            {
+                let (_, _, stages): (PathDOramClient<$type_n>, LocalServer<PathDOramClient<$type_n>>, _) =
+                    oram_stages(n_keys as u64);
+                Ok(stages)
+            }
+        }
+    }
+    match block_size {
+        8 => stages_for_block_size!(U8),
+        16 => stages_for_block_size!(U16),
+        32 => stages_for_block_size!(U32),
+        64 => stages_for_block_size!(U64),
+        128 => stages_for_block_size!(U128),
+        160 => stages_for_block_size!(U160),
+        256 => stages_for_block_size!(U256),
+        512 => stages_for_block_size!(U512),
+        1024 => stages_for_block_size!(U1024),
+        2048 => stages_for_block_size!(U2048),
+        4096 => stages_for_block_size!(U4096),
+        _ => {
+            progress!("[-] Block size not supported, please input one of {{8, 16, 32, ..., 512, 160, 1024, 2048, 4096}}");
+            Err(BenchError::Osm(OsmStatus::InvalidInput))
+        }
+    }
+}
+
+/// The setup of the ORAM benchmarks, timed stage by stage.
+pub fn oram_stages<N, C, M>(
+    num_items: u64
+) -> (
+    PathDOramClient<N, C, M>,
+    LocalServer<PathDOramClient<N, C, M>>,
+    Vec<Stage>,
+)
+    where
+        N: ArrayLength<u8> + EncN,
+        EncBlkSize<N>: ArrayLength<u8>,
+        C: Encryptor,
+        M: MerkleTree,
+{
+    let n = num_items as usize;
+    warn_combined_stage("tree");
+    let (oram_data_map, blocks_stage) = Stage::timed("blocks", n, || {
+        let mut oram_data_map = Vec::with_capacity(n);
+        for i in 0..num_items {
+            oram_data_map.push(
+                (
+                    OramKey::new(i),
+                    BlockContent::with_slice(&[(i as u8 % 128u8); 8]),
+                )
+            );
+        }
+        oram_data_map
+    });
+    let ((mut client, _), client_stage) = Stage::timed("client", n, || {
+        PathDOramClient::new(num_items, vec![])
+    });
+    let (server, tree_stage) = Stage::timed("tree", n, || client.local_setup(oram_data_map).unwrap());
+    (client, server, vec![blocks_stage, client_stage, tree_stage])
+}
+
+/// Runs a setup `trials` times, with the generator of a new point, and
+/// reports each of its stages as a benchmark of its own, named
+/// `<prefix>::<stage>`. Returns the number of failures.
+pub fn report<F>(reporter: &mut Reporter, prefix: &str, params: Params, trials: usize, mut run: F) -> usize
+    where F: FnMut(&mut WorkloadRng) -> Result<Vec<Stage>, BenchError>
+{
+    let (seed, mut rng) = reporter.next_point();
+    let params = Params { point_seed: Some(seed), ..params };
+    let mut stages: Vec<(Stage, Vec<f64>)> = Vec::new();
+    for trial in 0..trials {
+        match run(&mut rng) {
+            Ok(measured) => {
+                for stage in measured {
+                    match stages.iter().position(|&(ref s, _)| s.name == stage.name) {
+                        Some(i) => stages[i].1.push(stage.secs),
+                        None => stages.push((stage, vec![stage.secs])),
+                    }
+                }
+            },
+            Err(e) => {
+                let error = Some(format!("trial {}: {}", trial + 1, e));
+                return reporter.report(prefix, params, trials, &Latencies::host(Vec::new()), error);
+            },
+        }
+    }
+    let mut failures = 0;
+    for (stage, samples) in stages {
+        let params = Params { n_items: Some(stage.items), ..params };
+        let latencies = Latencies::host(samples);
+        failures += reporter.report(&format!("{}::{}", prefix, stage.name), params, trials, &latencies, None);
+    }
+    failures
+}
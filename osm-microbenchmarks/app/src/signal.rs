@@ -0,0 +1,104 @@
+//! Private contact discovery, as in Signal: the directory of registered
+//! users is stored in OSM under the hash of each phone number, with the
+//! user's account id as the value. A client sends the phone numbers in its
+//! address book and learns which of them are registered, and as whom.
+//!
+//! Every contact costs one search of range 1, whether or not it is
+//! registered, so the accesses to the server's storage reveal only the size
+//! of the batch. The results do not stay hidden, though: the batch ECALL
+//! returns every contact's status and account id to the host in plaintext,
+//! so the host learns which contacts are registered (those that do not
+//! come back as `KeyNotFound`) and as whom. A deployment would have the
+//! enclave encrypt the results for the client; this benchmark leaves that
+//! out, and measures the lookups only.
+
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use backend::EnclaveBackend;
+use batch::{self, Op};
+use errors::{BenchError, OsmStatus};
+use microbenchmarks::{self, Key, Value, WorkloadRng};
+use timing::Latencies;
+
+/// Number of batches every point averages over.
+pub const MEASUREMENTS: usize = 100;
+/// Contact list sizes to sweep.
+pub const BATCH_SIZES: [usize; 4] = [1, 10, 100, 1000];
+/// Directory sizes are halved down to this one.
+pub const MIN_DIRECTORY_SIZE: usize = 10000;
+/// Share of the contacts in a batch that are registered.
+const REGISTERED_FRACTION: f64 = 0.5;
+
+/// The key a phone number (as digits, with the country code) is stored
+/// under.
+pub fn phone_key(number: u64) -> Key {
+    microbenchmarks::hash_key(format!("+{}", number).as_bytes())
+}
+
+/// A random 11-digit number, as a North American number with country code.
+fn phone_number(rng: &mut WorkloadRng) -> u64 {
+    rng.gen_range(10_000_000_000, 20_000_000_000)
+}
+
+/// Registers `directory_size` users and runs `MEASUREMENTS` lookups of
+/// `batch_size` contacts each. Returns the latency of every batch.
+pub fn run<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    directory_size: usize,
+    batch_size: usize,
+) -> Result<Latencies, BenchError> {
+    progress!("\n[+] Directory: {}, Batch size: {}", directory_size, batch_size);
+    if directory_size == 0 || batch_size == 0 {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+    let mut directory: HashMap<u64, Value> = HashMap::with_capacity(directory_size);
+    while directory.len() < directory_size {
+        let account = rng.gen::<Value>();
+        directory.insert(phone_number(rng), account);
+    }
+    let registered: Vec<u64> = directory.keys().cloned().collect();
+    progress!("[+] Done with directory");
+
+    let mut contacts = Vec::with_capacity(MEASUREMENTS * batch_size);
+    for _ in 0..MEASUREMENTS * batch_size {
+        let contact = if rng.gen::<f64>() < REGISTERED_FRACTION {
+            *rng.choose(&registered).unwrap()
+        } else {
+            phone_number(rng)
+        };
+        contacts.push(contact);
+    }
+
+    let map: Vec<(Key, Value)> = directory.iter().map(|(&number, &account)| (phone_key(number), account)).collect();
+    let (osm_client, mut server) = microbenchmarks::setup_osm(backend, map.len(), map);
+    progress!("[+] Done with setup");
+
+    let session_id = microbenchmarks::open_session(backend, &osm_client, &mut server)?;
+    let ops: Vec<Op> = contacts.iter().map(|&number| Op::Search { key: phone_key(number), offset: 0, range: 1 }).collect();
+    let executed = microbenchmarks::execute_groups(backend, session_id, &ops, &batch::chunks(MEASUREMENTS, batch_size));
+    backend.osm_close(session_id)?;
+    let (results, latencies) = executed?;
+
+    let mut found = 0;
+    for (result, number) in results.iter().zip(contacts.iter()) {
+        let accounts = match *result {
+            Ok(ref accounts) => accounts.clone(),
+            Err(OsmStatus::KeyNotFound) => Vec::new(),
+            Err(e) => return Err(BenchError::Osm(e)),
+        };
+        let expected: Vec<Value> = directory.get(number).cloned().into_iter().collect();
+        if accounts != expected {
+            return Err(BenchError::IncorrectResult);
+        }
+        found += accounts.len();
+    }
+
+    progress!(
+        "[+] Batches: {}, registered contacts: {} of {}, avg. time per batch (s): {}",
+        MEASUREMENTS, found, contacts.len(), latencies.mean()
+    );
+    Ok(latencies)
+}
@@ -0,0 +1,150 @@
+//! Summary statistics over the per-operation latencies of repeated trials.
+//!
+//! Every benchmark returns the latency of each operation it timed, in
+//! seconds. A parameter point is run for several trials and the samples of
+//! all trials are pooled into one `Summary`.
+
+use std::f64;
+use std::fmt;
+
+/// Coefficient of variation above which a summary is flagged as noisy.
+pub const NOISY_CV: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub trials: usize,
+    pub samples: usize,
+    pub min: f64,
+    pub median: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Sample standard deviation (n - 1 in the denominator).
+    pub std_dev: f64,
+    /// 95% confidence interval of the mean, as `(low, high)`.
+    pub ci95: (f64, f64),
+}
+
+impl Summary {
+    /// Summarizes the samples of `trials` trials. Returns `None` if there
+    /// are no samples.
+    pub fn new(trials: usize, samples: &[f64]) -> Option<Summary> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let std_dev = if n > 1 {
+            let var = sorted.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1) as f64;
+            var.sqrt()
+        } else {
+            0.0
+        };
+        // A single sample says nothing about the spread.
+        let half_width = if n > 1 {
+            t_95(n - 1) * std_dev / (n as f64).sqrt()
+        } else {
+            f64::INFINITY
+        };
+        Some(Summary {
+            trials,
+            samples: n,
+            min: sorted[0],
+            median: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+            p99: percentile(&sorted, 99.0),
+            max: sorted[n - 1],
+            mean,
+            std_dev,
+            ci95: (mean - half_width, mean + half_width),
+        })
+    }
+
+    /// Standard deviation relative to the mean.
+    pub fn cv(&self) -> f64 {
+        if self.mean == 0.0 { 0.0 } else { self.std_dev / self.mean }
+    }
+
+    /// Whether the samples vary too much for the mean to be cited as is.
+    pub fn is_noisy(&self) -> bool {
+        self.cv() > NOISY_CV
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "trials: {}, samples: {}, min: {:e}, median: {:e}, p90: {:e}, p99: {:e}, max: {:e}, \
+             mean: {:e}, std. dev.: {:e}, 95% CI: [{:e}, {:e}] (s)",
+            self.trials, self.samples, self.min, self.median, self.p90, self.p99, self.max,
+            self.mean, self.std_dev, self.ci95.0, self.ci95.1
+        )?;
+        if self.is_noisy() {
+            write!(f, " [noisy: cv = {:.2}]", self.cv())?;
+        }
+        Ok(())
+    }
+}
+
+/// Nearest-rank percentile of an ascending, non-empty slice.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1) - 1]
+}
+
+/// Two-sided 95% critical value of Student's t distribution, for at least
+/// one degree of freedom.
+fn t_95(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+        2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+        2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+    ];
+    match degrees_of_freedom {
+        d if d <= TABLE.len() => TABLE[d - 1],
+        _ => 1.960,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_are_nearest_rank() {
+        let sorted: Vec<f64> = (1..11).map(|i| i as f64).collect();
+        assert_eq!(percentile(&sorted, 50.0), 5.0);
+        assert_eq!(percentile(&sorted, 90.0), 9.0);
+        assert_eq!(percentile(&sorted, 99.0), 10.0);
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&[3.0], 50.0), 3.0);
+    }
+
+    #[test]
+    fn t_table_ends_at_the_normal_value() {
+        assert_eq!(t_95(1), 12.706);
+        assert_eq!(t_95(30), 2.042);
+        assert_eq!(t_95(31), 1.960);
+    }
+
+    #[test]
+    fn summaries() {
+        assert_eq!(Summary::new(1, &[]), None);
+
+        let single = Summary::new(1, &[2.0]).unwrap();
+        assert_eq!(single.std_dev, 0.0);
+        assert_eq!(single.ci95, (f64::NEG_INFINITY, f64::INFINITY));
+
+        let summary = Summary::new(2, &[3.0, 1.0, 2.0]).unwrap();
+        assert_eq!((summary.min, summary.median, summary.max), (1.0, 2.0, 3.0));
+        assert_eq!(summary.mean, 2.0);
+        assert_eq!(summary.std_dev, 1.0);
+        let half_width = 4.303 / 3f64.sqrt();
+        assert_eq!(summary.ci95, (2.0 - half_width, 2.0 + half_width));
+        assert!(summary.is_noisy());
+    }
+}
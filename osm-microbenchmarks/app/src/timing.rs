@@ -0,0 +1,157 @@
+//! Conversion of the cycle counts measured inside the enclave to seconds,
+//! and the latencies that benchmarks report.
+//!
+//! Latencies come from one of two clocks, and every sample set says which:
+//! cycles counted inside the enclave around each operation, or wall-clock
+//! time on the host around the ECALLs. Only samples from the same clock are
+//! comparable. Enclave-clock samples also carry the host time of the ECALLs
+//! that produced them, so that the end-to-end cost is reported alongside.
+//!
+//! An enclave built without `enclave_timing` (the default, since RDTSC
+//! faults inside SGX1 enclaves) returns `UNTIMED` for every cycle count.
+//! Benchmarks that batch their operations then issue one ECALL per
+//! measurement instead (see `microbenchmarks::execute_groups`), so that
+//! every sample still has a host time of its own. The ORAM reads, which
+//! must all happen in one ECALL, can only report the mean of that ECALL,
+//! on the `Ecall` clock.
+
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use time;
+
+/// What the enclave returns in place of a cycle count when it was built
+/// without `enclave_timing`.
+pub const UNTIMED: u64 = !0;
+
+/// The clock a set of latencies was read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Clock {
+    /// Cycles counted inside the enclave around each operation. Excludes
+    /// enclave transitions, copying buffers across the boundary and
+    /// everything the host does.
+    Enclave,
+    /// Wall-clock time on the host around the ECALLs: end to end.
+    Host,
+    /// Wall-clock time on the host around an ECALL that ran many
+    /// operations, over their number: one sample per ECALL, not per
+    /// operation. Only the mean of such samples means anything.
+    Ecall,
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Clock::Enclave => write!(f, "enclave"),
+            Clock::Host => write!(f, "host"),
+            Clock::Ecall => write!(f, "ecall"),
+        }
+    }
+}
+
+/// The latency samples of a benchmark, in seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Latencies {
+    pub clock: Clock,
+    pub secs: Vec<f64>,
+    /// For enclave-clock samples, the host time of the ECALLs they were
+    /// measured in, in total.
+    pub host_secs: Option<f64>,
+}
+
+impl Latencies {
+    pub fn host(secs: Vec<f64>) -> Latencies {
+        Latencies { clock: Clock::Host, secs, host_secs: None }
+    }
+
+    /// In-enclave latencies, measured in ECALLs that took `host_secs` on
+    /// the host.
+    pub fn enclave(secs: Vec<f64>, host_secs: f64) -> Latencies {
+        Latencies { clock: Clock::Enclave, secs, host_secs: Some(host_secs) }
+    }
+
+    pub fn mean(&self) -> f64 {
+        mean(&self.secs)
+    }
+
+    /// The host time per sample: end to end, whichever the clock.
+    pub fn host_mean(&self) -> f64 {
+        match self.host_secs {
+            Some(total) if !self.secs.is_empty() => total / self.secs.len() as f64,
+            Some(_) => 0.0,
+            None => self.mean(),
+        }
+    }
+
+    /// Latencies of operations whose cycles the enclave counted, measured
+    /// in ECALLs that took `host_secs` on the host. If the enclave did not
+    /// count them, this is a single `Ecall` sample: `host_secs` per
+    /// operation.
+    pub fn from_cycles(cycles: &[u64], host_secs: f64) -> Latencies {
+        if is_timed(cycles) {
+            Latencies::enclave(secs(cycles), host_secs)
+        } else {
+            untimed(host_secs, cycles.len())
+        }
+    }
+
+    /// Pools the samples of another trial into these. Fails if the two
+    /// were read from different clocks.
+    pub fn extend(&mut self, other: Latencies) -> Result<(), String> {
+        if other.clock != self.clock {
+            return Err(format!("trials were timed with different clocks ({} and {})", self.clock, other.clock));
+        }
+        self.secs.extend(other.secs);
+        self.host_secs = match (self.host_secs, other.host_secs) {
+            (Some(a), Some(b)) => Some(a + b),
+            _ => None,
+        };
+        Ok(())
+    }
+}
+
+lazy_static! {
+    /// TSC ticks per second, calibrated once against the wall clock.
+    static ref TSC_FREQUENCY: f64 = {
+        let start_cycles = rdtsc();
+        let start = time::precise_time_s();
+        thread::sleep(Duration::from_millis(100));
+        let cycles = rdtsc() - start_cycles;
+        cycles as f64 / (time::precise_time_s() - start)
+    };
+}
+
+fn rdtsc() -> u64 {
+    unsafe { ::std::arch::x86_64::_rdtsc() }
+}
+
+pub fn cycles_to_secs(cycles: u64) -> f64 {
+    cycles as f64 / *TSC_FREQUENCY
+}
+
+/// Whether the enclave counted the cycles of these operations.
+pub fn is_timed(cycles: &[u64]) -> bool {
+    !cycles.contains(&UNTIMED)
+}
+
+/// The fallback for `n` operations the enclave did not time, run in ECALLs
+/// that took `host_secs`: their average host time, as one `Ecall` sample,
+/// since the individual operations were not measured.
+pub fn untimed(host_secs: f64, n: usize) -> Latencies {
+    let secs = if n == 0 { Vec::new() } else { vec![host_secs / n as f64] };
+    Latencies { clock: Clock::Ecall, secs, host_secs: None }
+}
+
+/// Converts per-operation cycle counts to seconds.
+pub fn secs(cycles: &[u64]) -> Vec<f64> {
+    cycles.iter().map(|&c| cycles_to_secs(c)).collect()
+}
+
+pub fn mean(secs: &[f64]) -> f64 {
+    if secs.is_empty() {
+        return 0.0;
+    }
+    secs.iter().sum::<f64>() / secs.len() as f64
+}
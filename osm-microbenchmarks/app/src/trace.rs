@@ -0,0 +1,193 @@
+//! Operation traces: recorded from benchmarks, or converted from real query
+//! logs, and replayed with `osm replay` / `oram replay`.
+//!
+//! A trace is a text file with one operation per line, preceded by its
+//! timestamp in nanoseconds since the start of the trace. Blank lines and
+//! lines starting with `#` are ignored. `load` lines are not operations but
+//! the pairs a store was set up with, which replay loads before it starts.
+//! Search ranges above `batch::MAX_RANGE` are rejected.
+//!
+//! ```text
+//! <ns> load <key> <value>
+//! <ns> search <key> <offset> <range>
+//! <ns> insert <key> <value>
+//! <ns> delete <key> <value>
+//! <ns> update <key> <old value> <new value>
+//! <ns> access <block index>
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use bincode;
+use path_oram::OramKey;
+use time;
+
+use batch::{Op, MAX_RANGE};
+use errors::BenchError;
+use microbenchmarks::{Key, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceOp {
+    /// A pair the store held before any operation.
+    Load { key: Key, value: Value },
+    Osm(Op),
+    /// A read of an ORAM block.
+    Access(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub timestamp_ns: u64,
+    pub op: TraceOp,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.timestamp_ns)?;
+        match self.op {
+            TraceOp::Load { key, value } => write!(f, "load {} {}", key, value),
+            TraceOp::Osm(Op::Search { key, offset, range }) => write!(f, "search {} {} {}", key, offset, range),
+            TraceOp::Osm(Op::Insert { key, value }) => write!(f, "insert {} {}", key, value),
+            TraceOp::Osm(Op::Delete { key, value }) => write!(f, "delete {} {}", key, value),
+            TraceOp::Osm(Op::Update { key, old, new }) => write!(f, "update {} {} {}", key, old, new),
+            TraceOp::Access(index) => write!(f, "access {}", index),
+        }
+    }
+}
+
+impl FromStr for TraceEntry {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let num = |i: usize| -> Result<u64, String> {
+            fields.get(i)
+                .ok_or_else(|| format!("missing field {}", i + 1))?
+                .parse::<u64>()
+                .map_err(|e| format!("field {}: {}", i + 1, e))
+        };
+        let arity = |n: usize| -> Result<(), String> {
+            if fields.len() == n { Ok(()) } else { Err(format!("expected {} fields, found {}", n, fields.len())) }
+        };
+        let timestamp_ns = num(0)?;
+        let op = match fields.get(1).cloned() {
+            Some("load") => {
+                arity(4)?;
+                TraceOp::Load { key: num(2)?, value: num(3)? }
+            },
+            Some("search") => {
+                arity(5)?;
+                let range = num(4)?;
+                if range > MAX_RANGE as u64 {
+                    return Err(format!("range {} is over the limit of {}", range, MAX_RANGE));
+                }
+                TraceOp::Osm(Op::Search { key: num(2)?, offset: num(3)? as usize, range: range as usize })
+            },
+            Some("insert") => {
+                arity(4)?;
+                TraceOp::Osm(Op::Insert { key: num(2)?, value: num(3)? })
+            },
+            Some("delete") => {
+                arity(4)?;
+                TraceOp::Osm(Op::Delete { key: num(2)?, value: num(3)? })
+            },
+            Some("update") => {
+                arity(5)?;
+                TraceOp::Osm(Op::Update { key: num(2)?, old: num(3)?, new: num(4)? })
+            },
+            Some("access") => {
+                arity(3)?;
+                TraceOp::Access(num(2)?)
+            },
+            Some(other) => return Err(format!("unknown operation `{}`", other)),
+            None => return Err("missing operation".to_string()),
+        };
+        Ok(TraceEntry { timestamp_ns, op })
+    }
+}
+
+/// Reads a whole trace, reporting the first malformed line.
+pub fn read(path: &str) -> Result<Vec<TraceEntry>, BenchError> {
+    let file = io::BufReader::new(fs::File::open(path)?);
+    let mut entries = Vec::new();
+    for (i, line) in file.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.parse() {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                progress!("[-] {}:{}: {}", path, i + 1, e);
+                return Err(BenchError::Io(io::ErrorKind::InvalidData));
+            },
+        }
+    }
+    Ok(entries)
+}
+
+/// Appends entries to a trace file, timestamping them relative to when the
+/// writer was created.
+pub struct TraceWriter {
+    out: io::BufWriter<fs::File>,
+    start_ns: u64,
+}
+
+impl TraceWriter {
+    pub fn create(path: &str) -> io::Result<TraceWriter> {
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        writeln!(out, "# <ns> <operation> <arguments>")?;
+        Ok(TraceWriter { out, start_ns: time::precise_time_ns() })
+    }
+
+    pub fn write(&mut self, op: TraceOp) -> io::Result<()> {
+        let entry = TraceEntry { timestamp_ns: time::precise_time_ns() - self.start_ns, op };
+        writeln!(self.out, "{}", entry)
+    }
+}
+
+/// The block index of an ORAM key, as traces store it. Keys are created
+/// with `OramKey::new(index)` and serialize as that index.
+pub fn oram_key_index(key: &OramKey) -> Option<u64> {
+    bincode::serialize(key).ok().and_then(|bytes| bincode::deserialize(&bytes).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_round_trip() {
+        let ops = [
+            TraceOp::Load { key: 1, value: 2 },
+            TraceOp::Osm(Op::Search { key: 3, offset: 4, range: 5 }),
+            TraceOp::Osm(Op::Insert { key: 6, value: 7 }),
+            TraceOp::Osm(Op::Delete { key: 8, value: 9 }),
+            TraceOp::Osm(Op::Update { key: 10, old: 11, new: 12 }),
+            TraceOp::Access(13),
+        ];
+        for (i, &op) in ops.iter().enumerate() {
+            let entry = TraceEntry { timestamp_ns: 1000 * i as u64, op };
+            assert_eq!(entry.to_string().parse::<TraceEntry>(), Ok(entry));
+        }
+        assert_eq!(
+            "0 load 18446744073709551615 0".parse::<TraceEntry>(),
+            Ok(TraceEntry { timestamp_ns: 0, op: TraceOp::Load { key: !0, value: 0 } })
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        let lines = [
+            "", "5", "5 load 1", "5 load 1 2 3", "5 search 1 2", "x access 1", "5 access -1", "5 scan 1",
+            "5 search 1 0 1048577",
+        ];
+        for line in lines.iter() {
+            assert!(line.parse::<TraceEntry>().is_err(), "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! The YCSB core workloads (Cooper et al., "Benchmarking Cloud Serving
+//! Systems with YCSB") on top of the oblivious sorted multimap.
+//!
+//! A record is a key with `values_per_key` values, which stand in for
+//! YCSB's fields. The operations map onto OSM as follows:
+//!
+//! ```text
+//! read               search(key, 0, 1)
+//! update             replace one value of the key (delete + insert)
+//! insert             insert a new key with all of its values
+//! scan               search(key, 0, range), range uniform in 1..=MAX_SCAN_LENGTH
+//! read-modify-write  read, then update
+//! ```
+//!
+//! All operations of a run are executed as a batch; the latency of an
+//! operation is the in-enclave time of the OSM operations it consists of,
+//! or, if the enclave does not count cycles, the host time of the ECALL
+//! they run in on their own.
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use rand::Rng;
+
+use backend::EnclaveBackend;
+use batch::Op;
+use distribution::{Distribution, Zipfian};
+use errors::{BenchError, OsmStatus};
+use microbenchmarks::{self, Key, Value, WorkloadRng};
+use timing::{self, Latencies};
+
+/// Skew of the Zipfian request distribution, as in YCSB.
+const ZIPF_SKEW: f64 = 0.99;
+/// Longest scan in workload E, as in YCSB.
+const MAX_SCAN_LENGTH: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Workload {
+    /// Update heavy: 50% reads, 50% updates.
+    A,
+    /// Read mostly: 95% reads, 5% updates.
+    B,
+    /// Read only.
+    C,
+    /// Read latest: 95% reads of recently inserted records, 5% inserts.
+    D,
+    /// Short ranges: 95% scans, 5% inserts.
+    E,
+    /// Read-modify-write: 50% reads, 50% read-modify-writes.
+    F,
+}
+
+impl FromStr for Workload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "a" => Ok(Workload::A),
+            "b" => Ok(Workload::B),
+            "c" => Ok(Workload::C),
+            "d" => Ok(Workload::D),
+            "e" => Ok(Workload::E),
+            "f" => Ok(Workload::F),
+            _ => Err(format!("unknown YCSB workload `{}` (expected a, b, c, d, e or f)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Read,
+    Update,
+    Insert,
+    Scan,
+    ReadModifyWrite,
+}
+
+const KINDS: [Kind; 5] = [Kind::Read, Kind::Update, Kind::Insert, Kind::Scan, Kind::ReadModifyWrite];
+
+impl Workload {
+    /// The proportion of each kind of operation.
+    fn mix(&self) -> &'static [(Kind, f64)] {
+        match *self {
+            Workload::A => &[(Kind::Read, 0.5), (Kind::Update, 0.5)],
+            Workload::B => &[(Kind::Read, 0.95), (Kind::Update, 0.05)],
+            Workload::C => &[(Kind::Read, 1.0)],
+            Workload::D => &[(Kind::Read, 0.95), (Kind::Insert, 0.05)],
+            Workload::E => &[(Kind::Scan, 0.95), (Kind::Insert, 0.05)],
+            Workload::F => &[(Kind::Read, 0.5), (Kind::ReadModifyWrite, 0.5)],
+        }
+    }
+
+    fn choose_kind(&self, rng: &mut WorkloadRng) -> Kind {
+        let mut x = rng.gen::<f64>();
+        let mix = self.mix();
+        for &(kind, p) in mix.iter() {
+            if x < p {
+                return kind;
+            }
+            x -= p;
+        }
+        mix[mix.len() - 1].0
+    }
+}
+
+/// What a search in the batch must return: `count` values, all from
+/// `allowed`.
+struct Check {
+    count: usize,
+    allowed: Vec<Value>,
+}
+
+/// One YCSB operation: a run of consecutive OSM operations in the batch.
+struct Logical {
+    kind: Kind,
+    start: usize,
+    len: usize,
+}
+
+/// Builds the batch, keeping a model of the store so that the result of
+/// every search is known in advance.
+struct Generator<'a> {
+    rng: &'a mut WorkloadRng,
+    records: Vec<(Key, Vec<Value>)>,
+    values_per_key: usize,
+    ops: Vec<Op>,
+    checks: Vec<Option<Check>>,
+    logical: Vec<Logical>,
+}
+
+impl<'a> Generator<'a> {
+    fn push(&mut self, op: Op, check: Option<Check>) {
+        self.ops.push(op);
+        self.checks.push(check);
+    }
+
+    fn read(&mut self, record: usize, range: usize) {
+        let (key, check) = {
+            let &(key, ref values) = &self.records[record];
+            (key, Check { count: range.min(values.len()), allowed: values.clone() })
+        };
+        self.push(Op::Search { key, offset: 0, range }, Some(check));
+    }
+
+    fn update(&mut self, record: usize) {
+        let field = self.rng.gen_range(0, self.values_per_key);
+        let new = self.rng.gen::<Value>();
+        let key = self.records[record].0;
+        let old = ::std::mem::replace(&mut self.records[record].1[field], new);
+        self.push(Op::Update { key, old, new }, None);
+    }
+
+    fn insert(&mut self) {
+        let key = self.rng.gen::<Key>();
+        let values: Vec<Value> = (0..self.values_per_key).map(|_| self.rng.gen()).collect();
+        for &value in values.iter() {
+            self.push(Op::Insert { key, value }, None);
+        }
+        self.records.push((key, values));
+    }
+
+    fn operation(&mut self, kind: Kind, record: usize) {
+        let start = self.ops.len();
+        match kind {
+            Kind::Read => self.read(record, 1),
+            Kind::Update => self.update(record),
+            Kind::Insert => self.insert(),
+            Kind::Scan => {
+                let range = self.rng.gen_range(1, MAX_SCAN_LENGTH + 1);
+                self.read(record, range);
+            },
+            Kind::ReadModifyWrite => {
+                self.read(record, 1);
+                self.update(record);
+            },
+        }
+        let len = self.ops.len() - start;
+        self.logical.push(Logical { kind, start, len });
+    }
+}
+
+/// Loads `record_count` records and runs `operation_count` operations of
+/// `workload` against them. Returns the latency of every operation.
+pub fn run<B: EnclaveBackend>(
+    backend: &B,
+    rng: &mut WorkloadRng,
+    workload: Workload,
+    record_count: usize,
+    operation_count: usize,
+    values_per_key: usize,
+) -> Result<Latencies, BenchError> {
+    progress!(
+        "\n[+] Workload: {}, Records: {}, Operations: {}, Values per key: {}",
+        workload, record_count, operation_count, values_per_key
+    );
+    if record_count == 0 || values_per_key == 0 {
+        return Err(BenchError::Osm(OsmStatus::InvalidInput));
+    }
+    let mut records = Vec::with_capacity(record_count + operation_count);
+    let mut map = Vec::with_capacity(record_count * values_per_key);
+    for _ in 0..record_count {
+        let key = rng.gen::<Key>();
+        let values: Vec<Value> = (0..values_per_key).map(|_| rng.gen()).collect();
+        map.extend(values.iter().map(|&v| (key, v)));
+        records.push((key, values));
+    }
+    progress!("[+] Done with map");
+
+    // Requests go to the records loaded up front, Zipfian over a random
+    // ranking; workload D instead favours the most recently inserted.
+    let requests = Distribution::Zipfian { skew: ZIPF_SKEW }.sample(rng, record_count, operation_count);
+    let latest = Zipfian::new(record_count, ZIPF_SKEW);
+    let mut gen = Generator {
+        rng,
+        records,
+        values_per_key,
+        ops: Vec::new(),
+        checks: Vec::new(),
+        logical: Vec::with_capacity(operation_count),
+    };
+    for &request in requests.iter() {
+        let kind = workload.choose_kind(gen.rng);
+        let record = if workload == Workload::D {
+            let age = latest.next(gen.rng).min(gen.records.len() - 1);
+            gen.records.len() - 1 - age
+        } else {
+            request
+        };
+        gen.operation(kind, record);
+    }
+    let Generator { ops, checks, logical, .. } = gen;
+
+    // Inserted records need room too.
+    let capacity = (record_count + operation_count) * values_per_key;
+    let (osm_client, mut server) = microbenchmarks::setup_osm(backend, capacity, map);
+    progress!("[+] Done with setup");
+
+    let session_id = microbenchmarks::open_session(backend, &osm_client, &mut server)?;
+    let groups: Vec<Range<usize>> = logical.iter().map(|op| op.start..op.start + op.len).collect();
+    let executed = microbenchmarks::execute_groups(backend, session_id, &ops, &groups);
+    backend.osm_close(session_id)?;
+    let (results, latencies) = executed?;
+
+    for (result, check) in results.iter().zip(checks.iter()) {
+        let values = result.clone().map_err(BenchError::Osm)?;
+        if let Some(ref check) = *check {
+            if values.len() != check.count || values.iter().any(|v| !check.allowed.contains(v)) {
+                return Err(BenchError::IncorrectResult);
+            }
+        }
+    }
+
+    // Only samples of single operations can be split by kind.
+    if latencies.secs.len() != logical.len() {
+        progress!("[-] No per-operation samples ({} clock); skipping the breakdown by kind", latencies.clock);
+        return Ok(latencies);
+    }
+    for kind in KINDS.iter() {
+        let of_kind: Vec<f64> = logical.iter().zip(latencies.secs.iter())
+            .filter(|&(op, _)| op.kind == *kind)
+            .map(|(_, &t)| t)
+            .collect();
+        if !of_kind.is_empty() {
+            progress!(
+                "[+] {:?}: {} operations, {} clock avg. time (s): {}",
+                kind, of_kind.len(), latencies.clock, timing::mean(&of_kind)
+            );
+        }
+    }
+    Ok(latencies)
+}
@@ -0,0 +1,109 @@
+//! Encoding of the operations and results of `osm_execute_batch`.
+//!
+//! Both directions are flat arrays of little-endian `u64` words. Every
+//! operation takes `OP_WORDS` words, the first of which is its tag:
+//!
+//! ```text
+//! Search: | 0 | key | offset    | range     |
+//! Insert: | 1 | key | value     | 0         |
+//! Delete: | 2 | key | value     | 0         |
+//! Update: | 3 | key | old value | new value |
+//! ```
+//!
+//! Every result is `| status | n | value_1 | ... | value_n |`, where `status`
+//! is an `OsmStatus` and `n` is 0 for everything but a successful search.
+//! A failed operation does not stop the rest of the batch.
+
+use std::vec::Vec;
+
+use osm::OsmClient;
+
+use error::OsmStatus;
+use session::{OsmClientT, OsmServerT};
+use {Key, Value};
+
+pub const OP_WORDS: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    Search { key: Key, offset: usize, range: usize },
+    Insert { key: Key, value: Value },
+    Delete { key: Key, value: Value },
+    Update { key: Key, old: Value, new: Value },
+}
+
+impl Op {
+    pub fn decode(words: &[u64]) -> Result<Op, OsmStatus> {
+        match words[0] {
+            0 => Ok(Op::Search { key: words[1], offset: words[2] as usize, range: words[3] as usize }),
+            1 => Ok(Op::Insert { key: words[1], value: words[2] }),
+            2 => Ok(Op::Delete { key: words[1], value: words[2] }),
+            3 => Ok(Op::Update { key: words[1], old: words[2], new: words[3] }),
+            _ => Err(OsmStatus::InvalidInput),
+        }
+    }
+
+    /// Upper bound on the number of result words this operation produces,
+    /// or `None` if it does not fit in a `usize`.
+    pub fn result_words(&self) -> Option<usize> {
+        match *self {
+            Op::Search { range, .. } => range.checked_add(2),
+            _ => Some(2),
+        }
+    }
+
+    pub fn execute(&self, client: &mut OsmClientT, server: &mut OsmServerT) -> Result<Vec<Value>, OsmStatus> {
+        match *self {
+            Op::Search { key, offset, range } => Ok(client.search(&key, offset, range, server)?),
+            Op::Insert { key, value } => {
+                client.insert(key, value, server)?;
+                Ok(Vec::new())
+            },
+            Op::Delete { key, value } => {
+                client.delete(key, value, server)?;
+                Ok(Vec::new())
+            },
+            Op::Update { key, old, new } => {
+                client.delete(key, old, server)?;
+                client.insert(key, new, server)?;
+                Ok(Vec::new())
+            },
+        }
+    }
+}
+
+/// Decodes a batch, rejecting it if it is not a whole number of operations.
+pub fn decode(words: &[u64]) -> Result<Vec<Op>, OsmStatus> {
+    if words.len() % OP_WORDS != 0 {
+        return Err(OsmStatus::InvalidInput);
+    }
+    words.chunks(OP_WORDS).map(Op::decode).collect()
+}
+
+/// The number of result words a batch needs. The ranges come from the
+/// host, so a batch whose results could not be counted is rejected rather
+/// than allowed to wrap around.
+pub fn result_words(ops: &[Op]) -> Result<usize, OsmStatus> {
+    let mut total = 0usize;
+    for op in ops {
+        total = op.result_words()
+            .and_then(|words| total.checked_add(words))
+            .ok_or(OsmStatus::InvalidInput)?;
+    }
+    Ok(total)
+}
+
+/// Appends the encoding of one result to `out`.
+pub fn encode_result(result: Result<Vec<Value>, OsmStatus>, out: &mut Vec<u64>) {
+    match result {
+        Ok(values) => {
+            out.push(OsmStatus::Success as u64);
+            out.push(values.len() as u64);
+            out.extend_from_slice(&values);
+        },
+        Err(e) => {
+            out.push(e as u64);
+            out.push(0);
+        },
+    }
+}
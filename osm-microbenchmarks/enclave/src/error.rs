@@ -0,0 +1,51 @@
+//! Status codes returned by every ECALL.
+//!
+//! ECALLs never unwrap: an OSM or ORAM failure is turned into an `OsmStatus`
+//! and returned to the host as a `uint32_t`, so that a failed operation does
+//! not abort the whole enclave. The discriminants are part of the ECALL ABI
+//! and are mirrored by `app/src/errors.rs`.
+
+use osm;
+use path_oram;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OsmStatus {
+    Success = 0,
+    StashOverflow = 1,
+    KeyNotFound = 2,
+    IntegrityFailure = 3,
+    CapacityExceeded = 4,
+    InvalidSession = 5,
+    InvalidInput = 6,
+    BufferTooSmall = 7,
+    Unknown = 255,
+}
+
+impl From<path_oram::Error> for OsmStatus {
+    fn from(e: path_oram::Error) -> Self {
+        match e {
+            path_oram::Error::StashOverflow => OsmStatus::StashOverflow,
+            path_oram::Error::IntegrityCheckFailed => OsmStatus::IntegrityFailure,
+            _ => OsmStatus::Unknown,
+        }
+    }
+}
+
+impl From<osm::Error> for OsmStatus {
+    fn from(e: osm::Error) -> Self {
+        match e {
+            osm::Error::Oram(e) => e.into(),
+            osm::Error::KeyNotFound => OsmStatus::KeyNotFound,
+            osm::Error::CapacityExceeded => OsmStatus::CapacityExceeded,
+            _ => OsmStatus::Unknown,
+        }
+    }
+}
+
+/// Encodes the outcome of an ECALL as its return value.
+pub fn status(result: Result<(), OsmStatus>) -> u32 {
+    match result {
+        Ok(()) => OsmStatus::Success as u32,
+        Err(e) => e as u32,
+    }
+}
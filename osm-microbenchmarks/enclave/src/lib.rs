@@ -27,76 +27,316 @@
 // OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 #![crate_name = "osm_microbenchmarks"]
-#![crate_type = "staticlib"]
 
-#![cfg_attr(not(target_env = "sgx"), no_std)]
+#![cfg_attr(all(feature = "sgx", not(target_env = "sgx")), no_std)]
 #![cfg_attr(target_env = "sgx", feature(rustc_private))]
 
 extern crate sgx_types;
-#[cfg(not(target_env = "sgx"))]
+#[cfg(all(feature = "sgx", not(target_env = "sgx")))]
+#[macro_use]
 extern crate sgx_tstd as std;
+#[cfg(not(feature = "software_seal"))]
+extern crate sgx_tseal;
+#[cfg(feature = "software_seal")]
+extern crate crypto;
+#[cfg(feature = "software_seal")]
+extern crate rand;
 
 extern crate osm;
 extern crate path_oram;
 extern crate generic_array;
+extern crate serde;
+extern crate bincode;
+extern crate spin;
+#[macro_use]
+extern crate lazy_static;
 
-use generic_array::typenum::U160;
-use osm::{OsmClient, STDOsmClient};
-use path_oram::{LocalServer, PathDOramClient};
+use generic_array::typenum::{U8, U16, U32, U64, U128, U160, U256, U512, U1024, U2048, U4096};
+use generic_array::ArrayLength;
+use osm::OsmClient;
+use path_oram::{LocalServer, PathDOramClient, TreeOramClient, OramKey, OramPos, EncN, EncBlkSize};
 use std::slice;
+use std::vec::Vec;
 
-use sgx_types::*;
+mod batch;
+mod error;
+mod marshal;
+mod oblivious;
+mod seal;
+mod session;
+mod timing;
+
+use error::{status, OsmStatus};
+use marshal::StateKind;
+use session::{OsmClientT, OsmSession};
 
 type Key = u64;
 type Value = u64;
 
+/// The server is the untrusted ORAM storage: its buckets are encrypted and
+/// authenticated by the client, so it is accessed in place in host memory.
+unsafe fn server<'a, T>(server_ref: usize) -> &'a mut T {
+    &mut *(server_ref as *mut T)
+}
+
 #[no_mangle]
-pub extern "C" fn osm_search(osm_client_ref: usize, server_ref: usize, key_ref: usize, range: usize) -> sgx_status_t {
+pub extern "C" fn osm_open(client: *const u8, client_len: usize, server_ref: usize, session_id: *mut u64) -> u32 {
 
-    let osm_client = unsafe {
-        let osm_client = osm_client_ref as *mut STDOsmClient<Key, Value, PathDOramClient<U160>>;
-        &(*osm_client)
+    let bytes = unsafe { slice::from_raw_parts(client, client_len) };
+    let osm_client: OsmClientT = match marshal::decode_state(bytes, StateKind::Osm, 160) {
+        Some(c) => c,
+        None => return OsmStatus::InvalidInput as u32,
     };
 
-    let server = unsafe {
-        let server = server_ref as *mut LocalServer<PathDOramClient<U160>>;
-        &mut(*server)
-    };
+    let id = session::open(OsmSession::new(osm_client, server_ref));
+    unsafe { *session_id = id };
 
-    let ref mut read_key = unsafe {
-        let key = key_ref as *const Key;
-        *key
-    };
+    OsmStatus::Success as u32
+}
 
-    let mut osm_client = osm_client.clone();
-    let num_reads = 2000;
-    for _ in 0..num_reads {
-        osm_client.search(&read_key, 0, range, server).unwrap();
+#[no_mangle]
+pub extern "C" fn osm_close(session_id: u64) -> u32 {
+    if session::close(session_id) {
+        OsmStatus::Success as u32
+    } else {
+        OsmStatus::InvalidSession as u32
     }
+}
 
-    sgx_status_t::SGX_SUCCESS
+/// Seals the current state of the session into `sealed`. If `sealed_cap` is
+/// too small, fails with `BufferTooSmall` and writes the required size to
+/// `sealed_len`.
+#[no_mangle]
+pub extern "C" fn osm_seal(session_id: u64, sealed: *mut u8, sealed_cap: usize, sealed_len: *mut usize) -> u32 {
+    let out = unsafe { slice::from_raw_parts_mut(sealed, sealed_cap) };
+    status(session::with_session(session_id, |session| {
+        let state = marshal::encode_state(&session.client, StateKind::Osm, 160)
+            .ok_or(OsmStatus::Unknown)?;
+        unsafe { *sealed_len = seal::sealed_size(state.len()) };
+        if seal::sealed_size(state.len()) > out.len() {
+            return Err(OsmStatus::BufferTooSmall);
+        }
+        let blob = seal::seal(&state)?;
+        out[..blob.len()].copy_from_slice(&blob);
+        unsafe { *sealed_len = blob.len() };
+        Ok(())
+    }))
 }
 
+/// Restores a sealed client into a new session over the given server.
 #[no_mangle]
-pub extern "C" fn osm_insert_many(osm_client_ref: usize, server_ref: usize, keys_ref: usize, keys_len: usize, values_ref: usize, values_len: usize) -> sgx_status_t {
+pub extern "C" fn osm_unseal(sealed: *const u8, sealed_len: usize, server_ref: usize, session_id: *mut u64) -> u32 {
+    let sealed = unsafe { slice::from_raw_parts(sealed, sealed_len) };
+    status(seal::unseal(sealed).and_then(|state| {
+        let osm_client: OsmClientT = marshal::decode_state(&state, StateKind::Osm, 160)
+            .ok_or(OsmStatus::InvalidInput)?;
+        let id = session::open(OsmSession::new(osm_client, server_ref));
+        unsafe { *session_id = id };
+        Ok(())
+    }))
+}
 
-    let osm_client = unsafe {
-        let osm_client = osm_client_ref as (*const STDOsmClient<Key, Value, PathDOramClient<U160>>);
-        &(*osm_client)
-    };
+/// Searches for `range` values of `key`, writing them to `values` and their
+/// number to `count`.
+#[no_mangle]
+pub extern "C" fn osm_search(session_id: u64, key: Key, range: usize, values: *mut Value, max_values: usize, count: *mut usize) -> u32 {
+    let out = unsafe { slice::from_raw_parts_mut(values, max_values) };
+    status(session::with_session(session_id, |session| {
+        let (osm_client, server) = session.parts();
+        let result = osm_client.search(&key, 0, range, server)?;
+        if result.len() > out.len() {
+            return Err(OsmStatus::BufferTooSmall);
+        }
+        out[..result.len()].copy_from_slice(&result);
+        unsafe { *count = result.len() };
+        Ok(())
+    }))
+}
 
-    let server = unsafe {
-        let server = server_ref as *mut LocalServer<PathDOramClient<U160>>;
-        &mut(*server)
-    };
+#[no_mangle]
+pub extern "C" fn osm_insert_many(session_id: u64, keys: *const Key, keys_len: usize, values: *const Value, values_len: usize) -> u32 {
+
+    let keys = unsafe { slice::from_raw_parts(keys, keys_len) };
+    let values = unsafe { slice::from_raw_parts(values, values_len) };
+
+    status(session::with_session(session_id, |session| {
+        let (osm_client, server) = session.parts();
+        for (&k, &v) in keys.iter().zip(values.iter()) {
+            osm_client.insert(k, v, server)?;
+        }
+        Ok(())
+    }))
+}
+
+#[no_mangle]
+pub extern "C" fn osm_insert_one(session_id: u64, key: Key, value: Value) -> u32 {
+    status(session::with_session(session_id, |session| {
+        let (osm_client, server) = session.parts();
+        osm_client.insert(key, value, server)?;
+        Ok(())
+    }))
+}
+
+#[no_mangle]
+pub extern "C" fn osm_delete_one(session_id: u64, key: Key, value: Value) -> u32 {
+    status(session::with_session(session_id, |session| {
+        let (osm_client, server) = session.parts();
+        osm_client.delete(key, value, server)?;
+        Ok(())
+    }))
+}
 
-    let keys = unsafe { slice::from_raw_parts(keys_ref as *const Key, keys_len) };
-    let values = unsafe { slice::from_raw_parts(values_ref as *const Key, values_len) };
+/// Executes a batch of encoded operations (see `batch.rs`) in order, writing
+/// the encoded results to `results` and their length to `results_len`, and
+/// the cycles spent on each operation to `cycles`.
+#[no_mangle]
+pub extern "C" fn osm_execute_batch(session_id: u64, ops: *const u64, ops_len: usize, results: *mut u64, results_cap: usize, results_len: *mut usize, cycles: *mut u64, cycles_len: usize) -> u32 {
+    let ops = unsafe { slice::from_raw_parts(ops, ops_len) };
+    let out = unsafe { slice::from_raw_parts_mut(results, results_cap) };
+    let cycles = unsafe { slice::from_raw_parts_mut(cycles, cycles_len) };
+    status(session::with_session(session_id, |session| {
+        let ops = batch::decode(ops)?;
+        if batch::result_words(&ops)? > out.len() || ops.len() > cycles.len() {
+            return Err(OsmStatus::BufferTooSmall);
+        }
+        let (osm_client, server) = session.parts();
+        let mut encoded = Vec::with_capacity(out.len());
+        for (op, c) in ops.iter().zip(cycles.iter_mut()) {
+            let (result, elapsed) = timing::timed(|| op.execute(osm_client, server));
+            *c = elapsed;
+            batch::encode_result(result, &mut encoded);
+        }
+        if encoded.len() > out.len() {
+            return Err(OsmStatus::BufferTooSmall);
+        }
+        out[..encoded.len()].copy_from_slice(&encoded);
+        unsafe { *results_len = encoded.len() };
+        Ok(())
+    }))
+}
+
+/// Searches for the values of every key in `keys` and writes their
+/// intersection (`conjunctive` != 0) or union to `values`, in ascending
+/// order, and its size to `count`. Every key is searched with range
+/// `max_postings`, and the lists are padded and merged obliviously (see
+/// `oblivious.rs` for what that leaves visible: the length of each list
+/// as the OSM client returns it). A missing key counts as an empty list.
+/// Every key is searched, and every list merged, even after a search
+/// fails, so that where a query fails does not show. `cycles` receives the
+/// cycles the whole query took.
+///
+/// Fails with `InvalidInput`, before searching, if `max_postings` is 0 or
+/// the lists would not fit in `oblivious::MAX_SLOTS`.
+#[no_mangle]
+pub extern "C" fn osm_multi_search(session_id: u64, keys: *const Key, keys_len: usize, conjunctive: u32, max_postings: usize, values: *mut Value, max_values: usize, count: *mut usize, cycles: *mut u64) -> u32 {
+    let keys = unsafe { slice::from_raw_parts(keys, keys_len) };
+    let out = unsafe { slice::from_raw_parts_mut(values, max_values) };
+    status(session::with_session(session_id, |session| {
+        if keys.is_empty() || max_postings == 0 || oblivious::slots(keys.len(), max_postings).is_none() {
+            return Err(OsmStatus::InvalidInput);
+        }
+        let (osm_client, server) = session.parts();
+        let (result, elapsed) = timing::timed(|| -> Result<Vec<Value>, OsmStatus> {
+            let mut lists = Vec::with_capacity(keys.len());
+            let mut failure = None;
+            for key in keys {
+                // A missing key is an empty list, which `merge` pads like
+                // any other. Other errors end the query, once the merge is
+                // done.
+                let (list, error) = match osm_client.search(key, 0, max_postings, server) {
+                    Ok(list) => (list, None),
+                    Err(osm::Error::KeyNotFound) => (Vec::new(), None),
+                    Err(e) => (Vec::new(), Some(OsmStatus::from(e))),
+                };
+                failure = failure.or(error);
+                lists.push(list);
+            }
+            let merged = oblivious::merge(lists, max_postings, conjunctive != 0);
+            match failure {
+                Some(e) => Err(e),
+                None => merged,
+            }
+        });
+        let result = result?;
+        if result.len() > out.len() {
+            return Err(OsmStatus::BufferTooSmall);
+        }
+        out[..result.len()].copy_from_slice(&result);
+        unsafe {
+            *count = result.len();
+            *cycles = elapsed;
+        }
+        Ok(())
+    }))
+}
+
+#[no_mangle]
+pub extern "C" fn oram_zerotrace(client: *const u8, client_len: usize, server_ref: usize, key_and_pos: *const u8, key_and_pos_len: usize, cycles: *mut u64, cycles_len: usize) -> u32 {
+    let cycles = unsafe { slice::from_raw_parts_mut(cycles, cycles_len) };
+    status(unsafe { read_with_positions::<U8>(client, client_len, server_ref, key_and_pos, key_and_pos_len, 8, cycles) })
+}
+
+/// Reads through a `PathDOramClient` of the given block size. The sizes
+/// here must cover every size the app can set a client up with (see
+/// `microbenchmarks::doram`); any other size is rejected.
+#[no_mangle]
+pub extern "C" fn oram_access(client: *const u8, client_len: usize, server_ref: usize, key_and_pos: *const u8, key_and_pos_len: usize, block_size: usize, cycles: *mut u64, cycles_len: usize) -> u32 {
+    let cycles = unsafe { slice::from_raw_parts_mut(cycles, cycles_len) };
+    macro_rules! read_for_block_size {
+        ($type_n:ty) => {
+            read_with_positions::<$type_n>(client, client_len, server_ref, key_and_pos, key_and_pos_len, block_size, cycles)
+        }
+    }
+    status(unsafe {
+        match block_size {
+            8 => read_for_block_size!(U8),
+            16 => read_for_block_size!(U16),
+            32 => read_for_block_size!(U32),
+            64 => read_for_block_size!(U64),
+            128 => read_for_block_size!(U128),
+            160 => read_for_block_size!(U160),
+            256 => read_for_block_size!(U256),
+            512 => read_for_block_size!(U512),
+            1024 => read_for_block_size!(U1024),
+            2048 => read_for_block_size!(U2048),
+            4096 => read_for_block_size!(U4096),
+            _ => Err(OsmStatus::InvalidInput),
+        }
+    })
+}
+
+/// Reads every `(key, pos)` pair through the client, using the supplied
+/// positions instead of the client's (emptied) position map, and records the
+/// cycles each read took.
+unsafe fn read_with_positions<N>(
+    client: *const u8,
+    client_len: usize,
+    server_ref: usize,
+    key_and_pos: *const u8,
+    key_and_pos_len: usize,
+    block_size: usize,
+    cycles: &mut [u64],
+) -> Result<(), OsmStatus>
+    where
+        N: ArrayLength<u8> + EncN,
+        EncBlkSize<N>: ArrayLength<u8>,
+{
+    let client = slice::from_raw_parts(client, client_len);
+    let mut oram_client: PathDOramClient<N> = marshal::decode_state(client, StateKind::DOram, block_size)
+        .ok_or(OsmStatus::InvalidInput)?;
+    let key_and_pos = slice::from_raw_parts(key_and_pos, key_and_pos_len);
+    let keys_and_positions: Vec<(OramKey, OramPos)> = marshal::decode(key_and_pos)
+        .ok_or(OsmStatus::InvalidInput)?;
+    if keys_and_positions.len() > cycles.len() {
+        return Err(OsmStatus::BufferTooSmall);
+    }
+    let server = server::<LocalServer<PathDOramClient<N>>>(server_ref);
 
-    let mut osm_client = osm_client.clone();
-    for (&k, &v) in keys.iter().zip(values.iter()) {
-        osm_client.insert(k, v, server).unwrap();
+    for (&(key, pos), c) in keys_and_positions.iter().zip(cycles.iter_mut()) {
+        let (result, elapsed) = timing::timed(|| oram_client.read_with_pos(key, pos, server));
+        result?;
+        *c = elapsed;
     }
 
-    sgx_status_t::SGX_SUCCESS
+    Ok(())
 }
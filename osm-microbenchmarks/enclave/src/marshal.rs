@@ -0,0 +1,67 @@
+//! Decoding of the client state that the untrusted side copies into the
+//! enclave.
+//!
+//! A state buffer is a fixed header followed by the bincode encoding of the
+//! client:
+//!
+//! ```text
+//! | magic "OBLX" (4) | version (1) | kind (1) | block size (4, LE) | bincode payload |
+//! ```
+//!
+//! The buffer arrives through an `[in]` EDL parameter, so by the time it is
+//! decoded here it has already been copied into trusted memory. The decoded
+//! client never leaves the enclave in the clear: the only way back out is
+//! `osm_seal`, which encrypts the encoded state first.
+
+use bincode;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::vec::Vec;
+
+pub const MAGIC: &'static [u8; 4] = b"OBLX";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateKind {
+    Osm = 0,
+    DOram = 1,
+}
+
+/// Encodes a client together with a header describing what it is.
+pub fn encode_state<T: Serialize>(client: &T, kind: StateKind, block_size: usize) -> Option<Vec<u8>> {
+    let payload = bincode::serialize(client).ok()?;
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.push(kind as u8);
+    let block_size = block_size as u32;
+    bytes.extend_from_slice(&[block_size as u8, (block_size >> 8) as u8, (block_size >> 16) as u8, (block_size >> 24) as u8]);
+    bytes.extend_from_slice(&payload);
+    Some(bytes)
+}
+
+/// Decodes a client state buffer, checking that its header matches the
+/// expected kind and block size.
+pub fn decode_state<T: DeserializeOwned>(bytes: &[u8], kind: StateKind, block_size: usize) -> Option<T> {
+    if bytes.len() < HEADER_LEN
+        || &bytes[0..4] != MAGIC
+        || bytes[4] != VERSION
+        || bytes[5] != kind as u8
+        || read_u32(&bytes[6..10]) as usize != block_size {
+        return None;
+    }
+    decode(&bytes[HEADER_LEN..])
+}
+
+/// Decodes a header-less bincode payload, such as a list of keys.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    bincode::deserialize(bytes).ok()
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32)
+        | (bytes[1] as u32) << 8
+        | (bytes[2] as u32) << 16
+        | (bytes[3] as u32) << 24
+}
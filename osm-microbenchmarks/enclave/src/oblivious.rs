@@ -0,0 +1,206 @@
+//! Oblivious intersection and union of posting lists.
+//!
+//! Every list is copied into a buffer of the same fixed width, padded with
+//! `DUMMY`: each slot of the buffer is chosen from every value of the list
+//! with a constant-time select, so nothing is read past the end of a list
+//! and every slot is written exactly once. Each buffer is then sorted and
+//! its repeated values replaced with `DUMMY`, and the buffers are merged
+//! with a bitonic sort, a linear scan and another bitonic sort. All of this
+//! touches memory in an order that depends only on the number of lists,
+//! the padded width and the length of each list's `Vec`.
+//!
+//! That last part is the one thing left to hide: the OSM client returns
+//! the values it found as a `Vec` of their real number, so the allocation
+//! and the number of reads made while padding (width × length) still
+//! follow each list's length. Hiding them needs an OSM search that fills a
+//! fixed-size buffer. Apart from that, the only thing the host learns is
+//! the size of the final result, which goes back to it anyway.
+//!
+//! Invalid input (a list longer than the padding, or a value equal to
+//! `DUMMY`) is detected without branching, and rejected once the merge is
+//! done.
+
+use std::vec::Vec;
+
+use Value;
+use error::OsmStatus;
+
+/// Padding; never a real value.
+pub const DUMMY: Value = !0;
+/// Most slots a merge may sort. Each takes 8 bytes, so this bounds a
+/// query to 32 MiB of the enclave heap.
+pub const MAX_SLOTS: usize = 1 << 22;
+
+/// The number of slots a merge of `n_lists` lists padded to `pad` sorts,
+/// or `None` if that would exceed `MAX_SLOTS`. Every list takes a
+/// power-of-two number of slots, so that it can be sorted on its own.
+pub fn slots(n_lists: usize, pad: usize) -> Option<usize> {
+    pad.checked_next_power_of_two()
+        .and_then(|width| n_lists.checked_mul(width))
+        .and_then(|n| n.checked_next_power_of_two())
+        .and_then(|n| if n <= MAX_SLOTS { Some(n) } else { None })
+}
+
+/// 1 if `a == b`, 0 otherwise, without branching.
+#[inline(always)]
+fn ct_eq(a: u64, b: u64) -> u64 {
+    let x = a ^ b;
+    ((x | x.wrapping_neg()) >> 63) ^ 1
+}
+
+/// `a` if `cond` is 1, `b` if it is 0, without branching.
+#[inline(always)]
+fn ct_select(cond: u64, a: u64, b: u64) -> u64 {
+    let mask = cond.wrapping_neg();
+    (a & mask) | (b & !mask)
+}
+
+/// Sorts `data`, whose length must be a power of two. Which elements are
+/// compared depends only on the length; whether they are swapped is
+/// computed without branching.
+pub fn bitonic_sort(data: &mut [u64]) {
+    let n = data.len();
+    debug_assert!(n.is_power_of_two() || n == 0);
+    let mut k = 2;
+    while k <= n {
+        let mut j = k / 2;
+        while j > 0 {
+            for i in 0..n {
+                let l = i ^ j;
+                if l > i {
+                    let (a, b) = (data[i], data[l]);
+                    let swap = if i & k == 0 { (a > b) as u64 } else { (a < b) as u64 };
+                    let t = (a ^ b) & swap.wrapping_neg();
+                    data[i] = a ^ t;
+                    data[l] = b ^ t;
+                }
+            }
+            j /= 2;
+        }
+        k *= 2;
+    }
+}
+
+/// Copies `list` into a buffer of `width` slots, sorted, with every value
+/// kept once and the remaining slots set to `DUMMY`. Returns 1 along with
+/// it if the list holds more than `pad` values or a `DUMMY`.
+fn pad_list(list: &[Value], pad: usize, width: usize) -> (Vec<Value>, u64) {
+    let mut invalid = (list.len() > pad) as u64;
+    for &value in list {
+        invalid |= ct_eq(value, DUMMY);
+    }
+    let mut padded = Vec::with_capacity(width);
+    for j in 0..width as u64 {
+        let mut slot = DUMMY;
+        for (i, &value) in list.iter().enumerate() {
+            slot = ct_select(ct_eq(i as u64, j), value, slot);
+        }
+        padded.push(slot);
+    }
+    bitonic_sort(&mut padded);
+
+    // Repeats are now adjacent; all but the first become padding.
+    let mut prev = DUMMY;
+    for v in padded.iter_mut() {
+        let value = *v;
+        *v = ct_select(ct_eq(value, prev), DUMMY, value);
+        prev = value;
+    }
+    bitonic_sort(&mut padded);
+    (padded, invalid)
+}
+
+/// Intersects (`conjunctive`) or unions the given lists, each of which
+/// holds at most `pad` values other than `DUMMY`. A value that repeats
+/// within a list counts once. Returns the result in ascending order, or
+/// `InvalidInput` if a list breaks those rules or the merge would need
+/// more than `MAX_SLOTS` slots.
+pub fn merge(lists: Vec<Vec<Value>>, pad: usize, conjunctive: bool) -> Result<Vec<Value>, OsmStatus> {
+    let n_lists = lists.len();
+    let n = slots(n_lists, pad).ok_or(OsmStatus::InvalidInput)?;
+    let width = pad.next_power_of_two();
+    let mut all = Vec::with_capacity(n);
+    let mut invalid = 0;
+    for list in lists {
+        let (padded, list_invalid) = pad_list(&list, pad, width);
+        invalid |= list_invalid;
+        all.extend_from_slice(&padded);
+    }
+    all.resize(n, DUMMY);
+    bitonic_sort(&mut all);
+
+    // Equal values are now adjacent, and each list contributes a value at
+    // most once. A value is kept at the position where its run reaches the
+    // required length: the first one for a union, the `lists.len()`-th
+    // (that is, present in every list) for an intersection. Everything
+    // else becomes padding.
+    let threshold = if conjunctive { n_lists as u64 } else { 1 };
+    let mut run = 0;
+    let mut prev = DUMMY;
+    let mut count = 0;
+    for v in all.iter_mut() {
+        let value = *v;
+        run = ct_select(ct_eq(value, prev), run, 0) + 1;
+        prev = value;
+        let keep = ct_eq(run, threshold) & (ct_eq(value, DUMMY) ^ 1);
+        *v = ct_select(keep, value, DUMMY);
+        count += keep;
+    }
+    bitonic_sort(&mut all);
+    if invalid != 0 {
+        return Err(OsmStatus::InvalidInput);
+    }
+    all.truncate(count as usize);
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts() {
+        let mut data = vec![5, 1, DUMMY, 3, 3, 0, 7, 2];
+        bitonic_sort(&mut data);
+        assert_eq!(data, vec![0, 1, 2, 3, 3, 5, 7, DUMMY]);
+    }
+
+    #[test]
+    fn intersects_and_unions() {
+        let lists = || vec![vec![4, 1, 9], vec![9, 2, 4, 7], vec![4, 9]];
+        assert_eq!(merge(lists(), 4, true), Ok(vec![4, 9]));
+        assert_eq!(merge(lists(), 4, false), Ok(vec![1, 2, 4, 7, 9]));
+        assert_eq!(merge(vec![vec![3]], 1, true), Ok(vec![3]));
+    }
+
+    #[test]
+    fn empty_lists() {
+        assert_eq!(merge(vec![vec![1, 2], vec![]], 2, true), Ok(vec![]));
+        assert_eq!(merge(vec![vec![1, 2], vec![]], 2, false), Ok(vec![1, 2]));
+        assert_eq!(merge(vec![vec![], vec![]], 2, false), Ok(vec![]));
+    }
+
+    #[test]
+    fn repeats_count_once() {
+        assert_eq!(merge(vec![vec![7, 7], vec![3]], 2, true), Ok(vec![]));
+        assert_eq!(merge(vec![vec![5, 1, 5], vec![1, 5]], 3, true), Ok(vec![1, 5]));
+        assert_eq!(merge(vec![vec![5, 5], vec![5]], 2, false), Ok(vec![5]));
+    }
+
+    #[test]
+    fn rejects_invalid_lists() {
+        assert_eq!(merge(vec![vec![1, 2, 3], vec![1]], 2, true), Err(OsmStatus::InvalidInput));
+        assert_eq!(merge(vec![vec![1, DUMMY]], 2, false), Err(OsmStatus::InvalidInput));
+        assert_eq!(merge(vec![vec![1]; 2], MAX_SLOTS, true), Err(OsmStatus::InvalidInput));
+    }
+
+    #[test]
+    fn slots_are_bounded() {
+        assert_eq!(slots(3, 5), Some(32));
+        assert_eq!(slots(3, 4), Some(16));
+        assert_eq!(slots(0, 5), Some(1));
+        assert_eq!(slots(1, MAX_SLOTS), Some(MAX_SLOTS));
+        assert_eq!(slots(2, MAX_SLOTS), None);
+        assert_eq!(slots(2, usize::max_value()), None);
+    }
+}
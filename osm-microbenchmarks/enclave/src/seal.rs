@@ -0,0 +1,110 @@
+//! Sealing of encoded client state, so that it can be stored by the host
+//! and restored after the enclave restarts.
+//!
+//! Inside SGX the state is sealed with the enclave's MRSIGNER-bound sealing
+//! key. With the `software_seal` feature, which is only meant for running
+//! the trusted code outside SGX, a fixed software key stands in for it and
+//! the state is protected with AES-128-GCM; that key provides no secrecy and
+//! exists so the seal/unseal path can be exercised without SGX hardware.
+
+use std::vec::Vec;
+
+use error::OsmStatus;
+
+/// Additional data bound to every sealed blob.
+const AAD: &'static [u8] = b"oblix-osm-client";
+
+#[cfg(not(feature = "software_seal"))]
+mod imp {
+    use std::vec::Vec;
+    use sgx_tseal::SgxSealedData;
+    use sgx_types::sgx_sealed_data_t;
+
+    use error::OsmStatus;
+
+    pub fn sealed_size(plaintext_len: usize) -> usize {
+        SgxSealedData::<[u8]>::calc_raw_sealed_data_size(super::AAD.len() as u32, plaintext_len as u32) as usize
+    }
+
+    pub fn seal(plaintext: &[u8]) -> Result<Vec<u8>, OsmStatus> {
+        let sealed = SgxSealedData::<[u8]>::seal_data(super::AAD, plaintext)
+            .map_err(|_| OsmStatus::Unknown)?;
+        let mut out = vec![0u8; sealed_size(plaintext.len())];
+        let len = out.len() as u32;
+        unsafe { sealed.to_raw_sealed_data_t(out.as_mut_ptr() as *mut sgx_sealed_data_t, len) }
+            .ok_or(OsmStatus::Unknown)?;
+        Ok(out)
+    }
+
+    pub fn unseal(sealed: &[u8]) -> Result<Vec<u8>, OsmStatus> {
+        let sealed = unsafe {
+            SgxSealedData::<[u8]>::from_raw_sealed_data_t(sealed.as_ptr() as *mut sgx_sealed_data_t, sealed.len() as u32)
+        }.ok_or(OsmStatus::InvalidInput)?;
+        if sealed.get_additional_txt() != super::AAD {
+            return Err(OsmStatus::IntegrityFailure);
+        }
+        let unsealed = sealed.unseal_data().map_err(|_| OsmStatus::IntegrityFailure)?;
+        Ok(unsealed.get_decrypt_txt().to_vec())
+    }
+}
+
+#[cfg(feature = "software_seal")]
+mod imp {
+    use std::vec::Vec;
+    use crypto::aead::{AeadDecryptor, AeadEncryptor};
+    use crypto::aes::KeySize;
+    use crypto::aes_gcm::AesGcm;
+    use rand::{OsRng, Rng};
+
+    use error::OsmStatus;
+
+    /// Stand-in for the SGX sealing key. Not secret.
+    const SOFTWARE_SEAL_KEY: [u8; 16] = *b"osm-test-sealkey";
+    const NONCE_LEN: usize = 12;
+    const TAG_LEN: usize = 16;
+
+    pub fn sealed_size(plaintext_len: usize) -> usize {
+        NONCE_LEN + plaintext_len + TAG_LEN
+    }
+
+    /// The sealed blob is the nonce, the ciphertext, then the tag.
+    pub fn seal(plaintext: &[u8]) -> Result<Vec<u8>, OsmStatus> {
+        let mut out = vec![0u8; sealed_size(plaintext.len())];
+        {
+            let (nonce, rest) = out.split_at_mut(NONCE_LEN);
+            let (ciphertext, tag) = rest.split_at_mut(plaintext.len());
+            OsRng::new().map_err(|_| OsmStatus::Unknown)?.fill_bytes(nonce);
+            AesGcm::new(KeySize::KeySize128, &SOFTWARE_SEAL_KEY, nonce, super::AAD)
+                .encrypt(plaintext, ciphertext, tag);
+        }
+        Ok(out)
+    }
+
+    pub fn unseal(sealed: &[u8]) -> Result<Vec<u8>, OsmStatus> {
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err(OsmStatus::InvalidInput);
+        }
+        let (nonce, rest) = sealed.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        let mut plaintext = vec![0u8; ciphertext.len()];
+        if AesGcm::new(KeySize::KeySize128, &SOFTWARE_SEAL_KEY, nonce, super::AAD)
+            .decrypt(ciphertext, &mut plaintext, tag) {
+            Ok(plaintext)
+        } else {
+            Err(OsmStatus::IntegrityFailure)
+        }
+    }
+}
+
+/// Size of the sealed blob for `plaintext_len` bytes of state.
+pub fn sealed_size(plaintext_len: usize) -> usize {
+    imp::sealed_size(plaintext_len)
+}
+
+pub fn seal(plaintext: &[u8]) -> Result<Vec<u8>, OsmStatus> {
+    imp::seal(plaintext)
+}
+
+pub fn unseal(sealed: &[u8]) -> Result<Vec<u8>, OsmStatus> {
+    imp::unseal(sealed)
+}
@@ -0,0 +1,82 @@
+//! Live OSM clients that persist across ECALLs.
+//!
+//! `osm_open` decodes a client into trusted memory and registers it here
+//! under an opaque session id; every later operation on that id updates the
+//! same client, so inserts and deletes are visible to subsequent searches.
+//!
+//! The enclave has several TCS, so ECALLs may run concurrently. The session
+//! table lock is only held to look a session up; each session then has its
+//! own lock, so threads working on different sessions (the expected setup:
+//! one session per client thread) never wait on each other, while threads
+//! sharing a session are serialized.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use spin::Mutex;
+
+use generic_array::typenum::U160;
+use osm::STDOsmClient;
+use path_oram::{LocalServer, PathDOramClient};
+
+use {Key, Value};
+use error::OsmStatus;
+
+pub type OsmClientT = STDOsmClient<Key, Value, PathDOramClient<U160>>;
+pub type OsmServerT = LocalServer<PathDOramClient<U160>>;
+
+pub struct OsmSession {
+    pub client: OsmClientT,
+    /// Address of the untrusted server that the client was set up against.
+    server_ref: usize,
+}
+
+impl OsmSession {
+    pub fn new(client: OsmClientT, server_ref: usize) -> Self {
+        OsmSession { client, server_ref }
+    }
+
+    /// Splits the session into the client and the server it talks to.
+    pub fn parts(&mut self) -> (&mut OsmClientT, &mut OsmServerT) {
+        let server = unsafe { &mut *(self.server_ref as *mut OsmServerT) };
+        (&mut self.client, server)
+    }
+}
+
+struct Sessions {
+    next_id: u64,
+    open: BTreeMap<u64, Arc<Mutex<OsmSession>>>,
+}
+
+lazy_static! {
+    static ref SESSIONS: Mutex<Sessions> = Mutex::new(Sessions {
+        next_id: 1,
+        open: BTreeMap::new(),
+    });
+}
+
+/// Registers `session` and returns its id. Ids are never reused, and `0` is
+/// never handed out.
+pub fn open(session: OsmSession) -> u64 {
+    let mut sessions = SESSIONS.lock();
+    let id = sessions.next_id;
+    sessions.next_id += 1;
+    sessions.open.insert(id, Arc::new(Mutex::new(session)));
+    id
+}
+
+/// Drops the session with the given id, returning whether it existed.
+pub fn close(id: u64) -> bool {
+    SESSIONS.lock().open.remove(&id).is_some()
+}
+
+/// Runs `f` on the session with the given id.
+pub fn with_session<F, R>(id: u64, f: F) -> Result<R, OsmStatus>
+    where F: FnOnce(&mut OsmSession) -> Result<R, OsmStatus>
+{
+    let session = match SESSIONS.lock().open.get(&id) {
+        Some(session) => session.clone(),
+        None => return Err(OsmStatus::InvalidSession),
+    };
+    let mut session = session.lock();
+    f(&mut session)
+}
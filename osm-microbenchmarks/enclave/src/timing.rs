@@ -0,0 +1,33 @@
+//! Cycle counter used to time individual operations inside the enclave, so
+//! that reported costs exclude enclave transitions and EDL marshalling.
+//!
+//! This reads the TSC directly, which is only legal inside an enclave on
+//! CPUs that permit it there (SGX2 and later); on SGX1 parts RDTSC faults
+//! and takes the enclave down. It is therefore opt-in: without the
+//! `enclave_timing` feature, operations are not timed and every cycle count
+//! comes back as `UNTIMED`, for the app to fall back to host time.
+
+/// Reported in place of a cycle count when the enclave cannot read the TSC.
+pub const UNTIMED: u64 = !0;
+
+#[cfg(feature = "enclave_timing")]
+#[inline(always)]
+pub fn cycles() -> u64 {
+    unsafe { ::std::arch::x86_64::_rdtsc() }
+}
+
+/// Runs `f` and returns its result together with the cycles it took.
+#[cfg(feature = "enclave_timing")]
+#[inline(always)]
+pub fn timed<T, F: FnOnce() -> T>(f: F) -> (T, u64) {
+    let start = cycles();
+    let result = f();
+    (result, cycles().wrapping_sub(start))
+}
+
+/// Runs `f` and returns its result together with `UNTIMED`.
+#[cfg(not(feature = "enclave_timing"))]
+#[inline(always)]
+pub fn timed<T, F: FnOnce() -> T>(f: F) -> (T, u64) {
+    (f(), UNTIMED)
+}